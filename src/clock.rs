@@ -0,0 +1,101 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::prelude::*;
+use tokio::timer::Delay;
+
+/// Source of time for scheduling a transmission.
+pub trait Clock: Clone {
+    fn now(&self) -> Instant;
+
+    /// A future that completes at `deadline`.
+    fn delay_until(&self, deadline: Instant) -> Box<dyn Future<Item = (), Error = ()> + Send>;
+}
+
+/// Real time, using the tokio timer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn delay_until(&self, deadline: Instant) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        Box::new(Delay::new(deadline).map_err(|_| ()))
+    }
+}
+
+/// Virtual time that only advances when a delay is requested.
+///
+/// Delays complete immediately, so a transmission can be run to completion without sleeping and
+/// every timestamp is deterministic. Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    /// Move time forward by `duration`, e.g. to simulate time spent publishing.
+    pub fn advance(&self, duration: std::time::Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn delay_until(&self, deadline: Instant) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        let mut now = self.now.lock().unwrap();
+        if deadline > *now {
+            *now = deadline;
+        }
+
+        Box::new(future::ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Clock, MockClock};
+    use std::time::{Duration, Instant};
+    use tokio::prelude::*;
+
+    #[test]
+    fn mock_delay() {
+        let start = Instant::now();
+        let clock = MockClock::new(start);
+
+        clock
+            .delay_until(start + Duration::from_secs(60))
+            .wait()
+            .unwrap();
+        assert_eq!(clock.now(), start + Duration::from_secs(60));
+
+        // Time does not go backwards.
+        clock.delay_until(start).wait().unwrap();
+        assert_eq!(clock.clone().now(), start + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn mock_advance() {
+        let start = Instant::now();
+        let clock = MockClock::new(start);
+
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(clock.now(), start + Duration::from_millis(5));
+    }
+}
@@ -3,5 +3,7 @@ mod mark;
 mod phrase;
 mod word;
 
+pub use phrase::DecodePhraseError as DecodeCodeError;
+pub use phrase::ParseMorseError;
 pub use phrase::Phrase as Code;
 pub use word::ParseWordError as ParseCodeError;
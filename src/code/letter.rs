@@ -1,6 +1,7 @@
 use crate::code::mark::Mark;
-use crate::timing::Signal;
-use std::collections::HashSet;
+use crate::code::phrase::DecodePhraseError;
+use crate::timing::{Signal, Symbol};
+use std::collections::{HashMap, HashSet};
 use std::convert::{From, TryFrom};
 use std::fmt;
 
@@ -60,9 +61,29 @@ lazy_static! {
         s.insert('/');
         s
     };
+    static ref PROSIGNS: HashMap<&'static str, Letter> = {
+        let mut m = HashMap::new();
+        m.insert("AR", Letter::ProsignAR);
+        m.insert("SK", Letter::ProsignSK);
+        m.insert("BT", Letter::ProsignBT);
+        m.insert("KN", Letter::ProsignKN);
+        m.insert("CT", Letter::ProsignCT);
+        m.insert("SOS", Letter::ProsignSOS);
+        m.insert("HH", Letter::ProsignHH);
+        m.insert("ERROR", Letter::ProsignHH);
+        m
+    };
+    // Prosigns that share a code with a character decode as the prosign: AR rather than '+', BT
+    // rather than '=' and KN rather than '('. Later entries replace earlier ones.
+    static ref LETTERS_BY_CODE: HashMap<&'static str, Letter> = VALID_LETTERS
+        .iter()
+        .map(|c| Letter::from(*c))
+        .chain(PROSIGNS.values().copied())
+        .map(|l| (l.str_ref(), l))
+        .collect();
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub(super) enum Letter {
     A,
     B,
@@ -115,6 +136,13 @@ pub(super) enum Letter {
     Quote,
     Query,
     Slash,
+    ProsignAR,
+    ProsignSK,
+    ProsignBT,
+    ProsignKN,
+    ProsignCT,
+    ProsignSOS,
+    ProsignHH,
 }
 
 impl Letter {
@@ -171,6 +199,76 @@ impl Letter {
             Self::Quote => ".-..-.",
             Self::Query => "..--..",
             Self::Slash => "-..-.",
+            Self::ProsignAR => ".-.-.",
+            Self::ProsignSK => "...-.-",
+            Self::ProsignBT => "-...-",
+            Self::ProsignKN => "-.--.",
+            Self::ProsignCT => "-.-.-",
+            Self::ProsignSOS => "...---...",
+            Self::ProsignHH => "........",
+        }
+    }
+
+    pub(super) fn text(&self) -> &'static str {
+        match self {
+            Self::A => "A",
+            Self::B => "B",
+            Self::C => "C",
+            Self::D => "D",
+            Self::E => "E",
+            Self::F => "F",
+            Self::G => "G",
+            Self::H => "H",
+            Self::I => "I",
+            Self::J => "J",
+            Self::K => "K",
+            Self::L => "L",
+            Self::M => "M",
+            Self::N => "N",
+            Self::O => "O",
+            Self::P => "P",
+            Self::Q => "Q",
+            Self::R => "R",
+            Self::S => "S",
+            Self::T => "T",
+            Self::U => "U",
+            Self::V => "V",
+            Self::W => "W",
+            Self::X => "X",
+            Self::Y => "Y",
+            Self::Z => "Z",
+            Self::Digit0 => "0",
+            Self::Digit1 => "1",
+            Self::Digit2 => "2",
+            Self::Digit3 => "3",
+            Self::Digit4 => "4",
+            Self::Digit5 => "5",
+            Self::Digit6 => "6",
+            Self::Digit7 => "7",
+            Self::Digit8 => "8",
+            Self::Digit9 => "9",
+            Self::Ampersand => "&",
+            Self::Apostrophe => "'",
+            Self::At => "@",
+            Self::BracketClose => ")",
+            Self::BracketOpen => "(",
+            Self::Colon => ":",
+            Self::Comma => ",",
+            Self::Equal => "=",
+            Self::Bang => "!",
+            Self::Period => ".",
+            Self::Hyphen => "-",
+            Self::Plus => "+",
+            Self::Quote => "\"",
+            Self::Query => "?",
+            Self::Slash => "/",
+            Self::ProsignAR => "<AR>",
+            Self::ProsignSK => "<SK>",
+            Self::ProsignBT => "<BT>",
+            Self::ProsignKN => "<KN>",
+            Self::ProsignCT => "<CT>",
+            Self::ProsignSOS => "<SOS>",
+            Self::ProsignHH => "<HH>",
         }
     }
 
@@ -178,12 +276,44 @@ impl Letter {
         self.str_ref().chars().map(Mark::from).collect()
     }
 
-    pub(super) fn timing(&self) -> impl Iterator<Item = Signal> {
+    pub(super) fn symbols(&self) -> impl Iterator<Item = Symbol> {
         self.marks()
             .into_iter()
-            .flat_map(|m| std::iter::once(Signal::Off).chain(m.timing()))
+            .flat_map(|m| std::iter::once(Symbol::MarkGap).chain(std::iter::once(m.symbol())))
             .skip(1) // Ignore the first mark gap
     }
+
+    /// Decode a letter from runs of signal units, as produced by `Symbol::timing()` after
+    /// grouping.
+    ///
+    /// Codes shared by a prosign and a character decode as the prosign, see `from_code()`. Eight
+    /// dots decode as the error prosign `<HH>`, while a longer run of dots is unknown.
+    pub(super) fn from_timing(runs: &[(Signal, usize)]) -> Result<Self, DecodePhraseError> {
+        let code = runs
+            .iter()
+            .filter_map(|&(signal, length)| match (signal, length) {
+                (Signal::On, 1) => Some(Ok(Mark::Dot)),
+                (Signal::On, 3) => Some(Ok(Mark::Dash)),
+                (Signal::Off, 1) => None, // Mark gap
+                _ => Some(Err(DecodePhraseError::InvalidSignal { signal, length })),
+            })
+            .map(|m| m.map(|m| m.to_string()))
+            .collect::<Result<String, _>>()?;
+
+        Self::from_code(&code).ok_or(DecodePhraseError::UnknownLetter { code })
+    }
+
+    /// Look up a letter from its dot/dash notation, e.g. ".-" for 'A'.
+    ///
+    /// `.-.-.`, `-...-` and `-.--.` are the prosigns AR, BT and KN, not '+', '=' and '('.
+    pub(super) fn from_code(code: &str) -> Option<Self> {
+        LETTERS_BY_CODE.get(code).copied()
+    }
+
+    /// Look up a prosign from its name, e.g. "SK".
+    pub(super) fn from_prosign(name: &str) -> Option<Self> {
+        PROSIGNS.get(name).copied()
+    }
 }
 
 impl From<char> for Letter {
@@ -267,16 +397,20 @@ impl fmt::Display for Letter {
 mod test {
     use super::{Letter, VALID_LETTERS};
     use crate::code::mark::Mark;
+    use crate::code::phrase::DecodePhraseError;
+    use crate::timing::{Signal, Symbol};
     use std::convert::{From, TryFrom};
 
     #[test]
     #[should_panic]
+    #[allow(unused_must_use)]
     fn invalid_char_a() {
         Letter::from('a');
     }
 
     #[test]
     #[should_panic]
+    #[allow(unused_must_use)]
     fn invalid_char_space() {
         Letter::from(' ');
     }
@@ -314,7 +448,7 @@ mod test {
     }
 
     #[test]
-    #[allow(non_snake_case)]
+    #[allow(non_snake_case, clippy::unnecessary_fallible_conversions)]
     fn try_from_M() {
         assert_eq!(Letter::try_from('M').unwrap().to_string(), "--");
     }
@@ -335,4 +469,83 @@ mod test {
     fn try_from_space() {
         assert!(Letter::try_from(&' ').is_err());
     }
+
+    #[test]
+    fn text() {
+        for c in VALID_LETTERS.iter() {
+            assert_eq!(Letter::from(*c).text(), c.to_string());
+        }
+        assert_eq!(Letter::ProsignSK.text(), "<SK>");
+    }
+
+    #[test]
+    fn from_prosign() {
+        assert_eq!(Letter::from_prosign("AR"), Some(Letter::ProsignAR));
+        assert_eq!(Letter::from_prosign("ERROR"), Some(Letter::ProsignHH));
+        assert_eq!(Letter::from_prosign("XX"), None);
+        assert_eq!(Letter::from_prosign("ar"), None);
+    }
+
+    #[test]
+    fn prosign_timing() {
+        assert_eq!(
+            Symbol::timing(Letter::ProsignSOS.symbols())
+                .map(|x| x.to_string())
+                .collect::<String>(),
+            "=.=.=.===.===.===.=.=.="
+        );
+    }
+
+    #[test]
+    fn from_code() {
+        assert_eq!(Letter::from_code("--"), Some(Letter::M));
+        assert_eq!(Letter::from_code(".-.-.-"), Some(Letter::Period));
+        assert_eq!(Letter::from_code(""), None);
+        assert_eq!(Letter::from_code(".-.-.-.-"), None);
+        assert_eq!(Letter::from_code("...-.-"), Some(Letter::ProsignSK));
+        assert_eq!(Letter::from_code(".-.-."), Some(Letter::ProsignAR));
+        assert_eq!(Letter::from_code("-...-"), Some(Letter::ProsignBT));
+        assert_eq!(Letter::from_code("-.--."), Some(Letter::ProsignKN));
+        assert_eq!(Letter::from_code("........"), Some(Letter::ProsignHH));
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn from_timing_K() {
+        let runs = [
+            (Signal::On, 3),
+            (Signal::Off, 1),
+            (Signal::On, 1),
+            (Signal::Off, 1),
+            (Signal::On, 3),
+        ];
+        assert_eq!(Letter::from_timing(&runs).unwrap(), Letter::K);
+    }
+
+    #[test]
+    fn from_timing_unknown() {
+        let runs = [(Signal::On, 1), (Signal::Off, 1)]
+            .iter()
+            .cycle()
+            .take(17)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            Letter::from_timing(&runs),
+            Err(DecodePhraseError::UnknownLetter {
+                code: ".........".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn from_timing_invalid_signal() {
+        assert_eq!(
+            Letter::from_timing(&[(Signal::On, 2)]),
+            Err(DecodePhraseError::InvalidSignal {
+                signal: Signal::On,
+                length: 2
+            })
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use crate::timing::Signal;
+use crate::timing::Symbol;
 use std::convert;
 use std::fmt;
 
@@ -9,10 +9,10 @@ pub(super) enum Mark {
 }
 
 impl Mark {
-    pub(super) fn timing(&self) -> impl Iterator<Item = Signal> {
+    pub(super) fn symbol(&self) -> Symbol {
         match self {
-            Self::Dot => std::iter::repeat(Signal::On).take(1),
-            Self::Dash => std::iter::repeat(Signal::On).take(3),
+            Self::Dot => Symbol::Dot,
+            Self::Dash => Symbol::Dash,
         }
     }
 }
@@ -56,6 +56,7 @@ mod test {
 
     #[test]
     #[should_panic]
+    #[allow(unused_must_use)]
     fn invalid_char() {
         Mark::from('a');
     }
@@ -1,29 +1,188 @@
+use crate::code::letter::Letter;
 use crate::code::word::{ParseWordError, Word};
-use crate::timing::Signal;
+use crate::timing::{Signal, Speed, Symbol};
+use itertools::Itertools;
+use std::error;
 use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
 
+#[derive(Debug, PartialEq)]
+pub enum DecodePhraseError {
+    InvalidSignal { signal: Signal, length: usize },
+    UnknownLetter { code: String },
+}
+
+impl fmt::Display for DecodePhraseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidSignal { signal, length } => write!(
+                f,
+                "Invalid Morse Code {} length: {}",
+                match signal {
+                    Signal::On => "mark",
+                    Signal::Off => "gap",
+                },
+                length
+            ),
+            Self::UnknownLetter { code } => write!(f, "Unknown Morse Code letter: {}", code),
+        }
+    }
+}
+
+impl error::Error for DecodePhraseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseMorseError {
+    group: String,
+    position: usize,
+}
+
+impl ParseMorseError {
+    /// The group of marks that could not be parsed.
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// Byte offset of the group in the parsed string.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for ParseMorseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid Morse Code group at position {}: {}",
+            self.position, self.group
+        )
+    }
+}
+
+impl error::Error for ParseMorseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Phrase {
     words: Vec<Word>,
 }
 
 impl Phrase {
-    pub fn timing<'a>(&'a self) -> impl Iterator<Item = Signal> + 'a {
+    /// Marks and gaps of the phrase, in order.
+    pub fn symbols<'a>(&'a self) -> impl Iterator<Item = Symbol> + 'a {
         self.words
             .iter()
-            .flat_map(|w| std::iter::repeat(Signal::Off).take(7).chain(w.timing()))
-            .skip(7) // Ignore the first word gap
+            .flat_map(|w| std::iter::once(Symbol::WordGap).chain(w.symbols()))
+            .skip(1) // Ignore the first word gap
     }
 
-    pub fn into_timing(self) -> impl Iterator<Item = Signal> {
+    pub fn into_symbols(self) -> impl Iterator<Item = Symbol> {
         self.words
             .into_iter()
-            .flat_map(move |w| {
-                std::iter::repeat(Signal::Off)
-                    .take(7)
-                    .chain(w.into_timing())
-            })
-            .skip(7) // Ignore the first word gap
+            .flat_map(move |w| std::iter::once(Symbol::WordGap).chain(w.into_symbols()))
+            .skip(1) // Ignore the first word gap
+    }
+
+    /// Signal units of the phrase with standard timing.
+    pub fn timing<'a>(&'a self) -> impl Iterator<Item = Signal> + 'a {
+        Symbol::timing(self.symbols())
+    }
+
+    pub fn into_timing(self) -> impl Iterator<Item = Signal> {
+        Symbol::timing(self.into_symbols())
+    }
+
+    /// Decode a phrase from signal units, inverting `timing()`.
+    ///
+    /// Marks must be 1 or 3 units and gaps must be 1, 3 or 7 units. Leading and trailing gaps are
+    /// ignored.
+    pub fn from_timing(
+        timing: impl IntoIterator<Item = Signal>,
+    ) -> Result<Self, DecodePhraseError> {
+        let runs: Vec<_> = timing
+            .into_iter()
+            .group_by(|x| *x)
+            .into_iter()
+            .map(|(signal, group)| (signal, group.count()))
+            .collect();
+
+        let start = runs.iter().position(|&(s, _)| s == Signal::On);
+        let end = runs.iter().rposition(|&(s, _)| s == Signal::On);
+
+        let words = match (start, end) {
+            (Some(start), Some(end)) => runs[start..=end]
+                .split(|&r| r == (Signal::Off, 7))
+                .map(Word::from_timing)
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => Vec::new(),
+        };
+
+        Ok(Self { words })
+    }
+
+    /// Parse a phrase from dot/dash notation, as written by `Display`.
+    ///
+    /// Letters are separated by a single space. Words are separated by two or more spaces or by
+    /// `/`. An underscore is accepted in place of a dash.
+    pub fn from_morse(s: &str) -> Result<Self, ParseMorseError> {
+        let mut words = Vec::new();
+        let mut letters = Vec::new();
+        let mut chars = s.char_indices().peekable();
+
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() || c == '/' {
+                let mut spaces = 0;
+                let mut slash = false;
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_whitespace() || c == '/') {
+                    if c == '/' {
+                        slash = true;
+                    } else {
+                        spaces += 1;
+                    }
+                }
+
+                if (slash || spaces > 1) && !letters.is_empty() {
+                    words.push(Word::from(std::mem::take(&mut letters)));
+                }
+            } else {
+                let mut end = start;
+                while let Some((i, c)) = chars.next_if(|&(_, c)| !c.is_whitespace() && c != '/') {
+                    end = i + c.len_utf8();
+                }
+
+                let group = &s[start..end];
+                let letter =
+                    Letter::from_code(&group.replace('_', "-")).ok_or_else(|| ParseMorseError {
+                        group: group.to_string(),
+                        position: start,
+                    })?;
+                letters.push(letter);
+            }
+        }
+
+        if !letters.is_empty() {
+            words.push(Word::from(letters));
+        }
+
+        Ok(Self { words })
+    }
+
+    /// Plain text of the phrase, with words separated by a single space.
+    pub fn text(&self) -> String {
+        self.words.iter().map(Word::text).join(" ")
+    }
+
+    /// Time taken to transmit the phrase at `speed`.
+    pub fn transmission_time(&self, speed: &Speed) -> Duration {
+        speed.transmission_time(self.symbols())
     }
 
     pub fn is_empty(&self) -> bool {
@@ -60,7 +219,8 @@ impl fmt::Display for Phrase {
 
 #[cfg(test)]
 mod test {
-    use super::Phrase;
+    use super::{DecodePhraseError, ParseMorseError, Phrase};
+    use crate::timing::Signal;
 
     #[test]
     fn parse() {
@@ -83,4 +243,134 @@ mod test {
             "===.===...===.===.===...=.===.=...=.=.=...=.......===.=.===.=...===.===.===...===.=.=...="
         );
     }
+
+    #[test]
+    fn from_timing() {
+        let p = "MORSE CODE".parse::<Phrase>().unwrap();
+        assert_eq!(Phrase::from_timing(p.timing()).unwrap(), p);
+    }
+
+    #[test]
+    fn from_into_timing() {
+        let p = Phrase::from_timing("SOS 73".parse::<Phrase>().unwrap().into_timing()).unwrap();
+        assert_eq!(p.text(), "SOS 73");
+    }
+
+    #[test]
+    fn from_timing_padded() {
+        let timing = std::iter::repeat(Signal::Off)
+            .take(5)
+            .chain("E".parse::<Phrase>().unwrap().into_timing())
+            .chain(std::iter::repeat(Signal::Off).take(10));
+        assert_eq!(Phrase::from_timing(timing).unwrap().text(), "E");
+    }
+
+    #[test]
+    fn from_timing_empty() {
+        assert!(Phrase::from_timing(std::iter::empty()).unwrap().is_empty());
+        assert!(Phrase::from_timing(std::iter::repeat(Signal::Off).take(3))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn from_timing_invalid_gap() {
+        let timing = vec![Signal::On, Signal::Off, Signal::Off, Signal::On];
+        assert_eq!(
+            Phrase::from_timing(timing),
+            Err(DecodePhraseError::InvalidSignal {
+                signal: Signal::Off,
+                length: 2
+            })
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            "morse  code".parse::<Phrase>().unwrap().text(),
+            "MORSE CODE"
+        );
+    }
+
+    #[test]
+    fn from_morse() {
+        let p = Phrase::from_morse("-- --- .-. ... .   -.-. --- -.. .").unwrap();
+        assert_eq!(p.text(), "MORSE CODE");
+    }
+
+    #[test]
+    fn from_morse_display() {
+        let p = "MORSE CODE".parse::<Phrase>().unwrap();
+        assert_eq!(Phrase::from_morse(&p.to_string()).unwrap(), p);
+    }
+
+    #[test]
+    fn from_morse_slash() {
+        assert_eq!(
+            Phrase::from_morse(" ... --- .../___ .- / ..._  ")
+                .unwrap()
+                .text(),
+            "SOS OA V"
+        );
+    }
+
+    #[test]
+    fn from_morse_empty() {
+        assert!(Phrase::from_morse("").unwrap().is_empty());
+        assert!(Phrase::from_morse(" / ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn from_morse_invalid_group() {
+        assert_eq!(
+            Phrase::from_morse("... .-.-.-.- ..."),
+            Err(ParseMorseError {
+                group: ".-.-.-.-".to_string(),
+                position: 4
+            })
+        );
+    }
+
+    #[test]
+    fn from_morse_invalid_char() {
+        let e = Phrase::from_morse("-- x--").unwrap_err();
+        assert_eq!(e.group(), "x--");
+        assert_eq!(e.position(), 3);
+    }
+
+    #[test]
+    fn prosign() {
+        let p = "CQ DE K1ABC <KN>".parse::<Phrase>().unwrap();
+        assert_eq!(
+            p.to_string(),
+            "-.-. --.-   -.. .   -.- .---- .- -... -.-.   -.--."
+        );
+        assert_eq!(p.text(), "CQ DE K1ABC <KN>");
+    }
+
+    #[test]
+    fn prosign_from_timing() {
+        let p = "73 <SK>".parse::<Phrase>().unwrap();
+        assert_eq!(Phrase::from_timing(p.timing()).unwrap(), p);
+    }
+
+    #[test]
+    fn prosign_round_trip() {
+        for text in &["<AR>", "<BT>", "<KN>", "<SK>", "<HH>"] {
+            let p = text.parse::<Phrase>().unwrap();
+            assert_eq!(Phrase::from_timing(p.timing()).unwrap(), p);
+            assert_eq!(Phrase::from_morse(&p.to_string()).unwrap(), p);
+        }
+    }
+
+    #[test]
+    fn prosign_shares_code() {
+        // The characters are sent as usual but decode as the prosigns with the same code.
+        let p = "1+1=2 (".parse::<Phrase>().unwrap();
+        assert_eq!(
+            Phrase::from_timing(p.timing()).unwrap().text(),
+            "1<AR>1<BT>2 <KN>"
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use crate::code::letter::Letter;
-use crate::timing::Signal;
+use crate::code::phrase::DecodePhraseError;
+use crate::timing::{Signal, Symbol};
 use std::convert::TryFrom;
 use std::error;
 use std::fmt;
@@ -7,12 +8,12 @@ use std::str::FromStr;
 
 #[derive(Debug)]
 pub struct ParseWordError {
-    c: char,
+    letter: String,
 }
 
 impl fmt::Display for ParseWordError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Invalid Morse Code letter: {}", self.c)
+        write!(f, "Invalid Morse Code letter: {}", self.letter)
     }
 }
 
@@ -22,23 +23,44 @@ impl error::Error for ParseWordError {
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub(super) struct Word {
     letters: Vec<Letter>,
 }
 
 impl Word {
-    pub(super) fn timing<'a>(&'a self) -> impl Iterator<Item = Signal> + 'a {
+    pub(super) fn symbols<'a>(&'a self) -> impl Iterator<Item = Symbol> + 'a {
         self.letters
             .iter()
-            .flat_map(|l| std::iter::repeat(Signal::Off).take(3).chain(l.timing()))
-            .skip(3) // Ignore the first letter gap
+            .flat_map(|l| std::iter::once(Symbol::LetterGap).chain(l.symbols()))
+            .skip(1) // Ignore the first letter gap
     }
 
-    pub(super) fn into_timing(self) -> impl Iterator<Item = Signal> {
+    pub(super) fn into_symbols(self) -> impl Iterator<Item = Symbol> {
         self.letters
             .into_iter()
-            .flat_map(move |l| std::iter::repeat(Signal::Off).take(3).chain(l.timing()))
-            .skip(3) // Ignore the first letter gap
+            .flat_map(move |l| std::iter::once(Symbol::LetterGap).chain(l.symbols()))
+            .skip(1) // Ignore the first letter gap
+    }
+
+    /// Decode a word from runs of signal units, split into letters on each letter gap.
+    pub(super) fn from_timing(runs: &[(Signal, usize)]) -> Result<Self, DecodePhraseError> {
+        let letters = runs
+            .split(|&r| r == (Signal::Off, 3))
+            .map(Letter::from_timing)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { letters })
+    }
+
+    pub(super) fn text(&self) -> String {
+        self.letters.iter().map(Letter::text).collect()
+    }
+}
+
+impl From<Vec<Letter>> for Word {
+    fn from(letters: Vec<Letter>) -> Self {
+        Self { letters }
     }
 }
 
@@ -46,11 +68,28 @@ impl FromStr for Word {
     type Err = ParseWordError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let letters = s
-            .to_uppercase()
-            .chars()
-            .map(|c| Letter::try_from(&c).or(Err(ParseWordError { c })))
-            .collect::<Result<Vec<_>, Self::Err>>()?;
+        let s = s.to_uppercase();
+        let mut rest = s.as_str();
+        let mut letters = Vec::new();
+
+        while let Some(c) = rest.chars().next() {
+            let (letter, len) = match c {
+                // Prosigns are written as <SK> or [SK].
+                '<' | '[' => {
+                    let close = if c == '<' { '>' } else { ']' };
+                    match rest.find(close) {
+                        Some(i) => (Letter::from_prosign(&rest[1..i]), i + 1),
+                        None => (None, rest.len()),
+                    }
+                }
+                _ => (Letter::try_from(&c).ok(), c.len_utf8()),
+            };
+
+            letters.push(letter.ok_or_else(|| ParseWordError {
+                letter: rest[..len].to_string(),
+            })?);
+            rest = &rest[len..];
+        }
 
         Ok(Self { letters })
     }
@@ -74,6 +113,8 @@ impl fmt::Display for Word {
 mod test {
     use super::Word;
     use crate::code::letter::Letter;
+    use crate::timing::Symbol;
+    use itertools::Itertools;
     use std::str::FromStr;
 
     #[test]
@@ -113,4 +154,55 @@ mod test {
     fn invalid_char() {
         assert!(Word::from_str("MORSE ").is_err());
     }
+
+    #[test]
+    fn from_timing() {
+        let w: Word = "MORSE".parse().unwrap();
+        let runs = Symbol::timing(w.symbols())
+            .group_by(|x| *x)
+            .into_iter()
+            .map(|(signal, group)| (signal, group.count()))
+            .collect::<Vec<_>>();
+        assert_eq!(Word::from_timing(&runs).unwrap(), w);
+    }
+
+    #[test]
+    fn text() {
+        let w: Word = "morse".parse().unwrap();
+        assert_eq!(w.text(), "MORSE");
+    }
+
+    #[test]
+    fn from_str_prosign() {
+        let w = Word::from_str("<sk>[AR]K").unwrap();
+        assert_eq!(
+            w.letters,
+            vec![Letter::ProsignSK, Letter::ProsignAR, Letter::K]
+        );
+        assert_eq!(w.text(), "<SK><AR>K");
+    }
+
+    #[test]
+    fn invalid_prosign() {
+        assert_eq!(
+            Word::from_str("<XX>").unwrap_err().to_string(),
+            "Invalid Morse Code letter: <XX>"
+        );
+        assert_eq!(
+            Word::from_str("K<SK").unwrap_err().to_string(),
+            "Invalid Morse Code letter: <SK"
+        );
+        assert!(Word::from_str("<SK]").is_err());
+    }
+
+    #[test]
+    fn prosign_timing() {
+        let w: Word = "<SOS>".parse().unwrap();
+        let letters: Word = "SOS".parse().unwrap();
+        // Letter gaps are replaced by mark gaps.
+        assert_eq!(
+            Symbol::timing(w.symbols()).count() + 4,
+            Symbol::timing(letters.symbols()).count()
+        );
+    }
 }
@@ -0,0 +1,188 @@
+use std::str::FromStr;
+use toml::Value;
+
+/// Settings read from a TOML file.
+///
+/// The schema is flat: every key is at the top level, and sections such as `[broker]` are
+/// rejected. Keys are the long names of command line options, with `_` accepted in place of `-`,
+/// e.g. `wpm = 20` or `daemon = true`. A flag set to `false` is off unless given on the command
+/// line. The positional arguments are given by `topic`, `on-payload` and `off-payload`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    options: Vec<(String, Setting)>,
+    topic: Option<String>,
+    on_payload: Option<String>,
+    off_payload: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Setting {
+    Flag(bool),
+    Value(String),
+}
+
+impl Config {
+    pub fn read(path: &str) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| s.parse())
+            .map_err(|e| format!("Error reading '{}': {}", path, e))
+    }
+
+    /// Command line arguments for the options where `include` returns true. Flags set to `false`
+    /// are left out.
+    pub fn args<F>(&self, mut include: F) -> Vec<String>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut args = Vec::new();
+        for (name, setting) in &self.options {
+            match setting {
+                Setting::Flag(false) => {}
+                _ if !include(name) => {}
+                Setting::Flag(true) => args.push(format!("--{}", name)),
+                Setting::Value(value) => args.extend([format!("--{}", name), value.clone()]),
+            }
+        }
+
+        args
+    }
+
+    /// Command line arguments for every option, with flags set to `false` given as if set, so
+    /// that a parser rejects those that aren't flags.
+    pub fn all_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (name, setting) in &self.options {
+            args.push(format!("--{}", name));
+            if let Setting::Value(value) = setting {
+                args.push(value.clone());
+            }
+        }
+
+        args
+    }
+
+    /// The topic, on payload and off payload, if all are given.
+    pub fn positional(&self) -> Option<Vec<String>> {
+        match (&self.topic, &self.on_payload, &self.off_payload) {
+            (Some(topic), Some(on), Some(off)) => {
+                Some(vec![topic.clone(), on.clone(), off.clone()])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let table = match s.parse::<Value>().map_err(|e| e.to_string())? {
+            Value::Table(table) => table,
+            _ => unreachable!("a TOML document is a table"),
+        };
+
+        let mut config = Self::default();
+        for (key, value) in table {
+            let name = key.replace('_', "-");
+            let setting = match value {
+                Value::String(s) => Setting::Value(s),
+                Value::Integer(i) => Setting::Value(i.to_string()),
+                Value::Float(f) => Setting::Value(f.to_string()),
+                Value::Boolean(b) => Setting::Flag(b),
+                Value::Table(_) => {
+                    return Err(format!(
+                        "'{}': sections aren't supported, options are top-level keys",
+                        key
+                    ))
+                }
+                _ => return Err(format!("'{}' must be a string, number or boolean", key)),
+            };
+
+            let positional = match name.as_str() {
+                "topic" => &mut config.topic,
+                "on-payload" => &mut config.on_payload,
+                "off-payload" => &mut config.off_payload,
+                _ => {
+                    config.options.push((name, setting));
+                    continue;
+                }
+            };
+            match setting {
+                Setting::Value(value) => *positional = Some(value),
+                Setting::Flag(_) => return Err(format!("'{}' must be a string", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+
+    #[test]
+    fn parse() {
+        let config: Config = r#"
+            host = "broker.local"
+            port = 1884
+            wpm = 12.5
+            daemon = true
+            morse = false
+            topic = "lamp/set"
+            on_payload = '{"state":"ON"}'
+            off-payload = '{"state":"OFF"}'
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            config.args(|_| true),
+            [
+                "--daemon",
+                "--host",
+                "broker.local",
+                "--port",
+                "1884",
+                "--wpm",
+                "12.5"
+            ]
+        );
+        assert_eq!(
+            config.args(|name| name != "host"),
+            ["--daemon", "--port", "1884", "--wpm", "12.5"]
+        );
+        assert_eq!(
+            config.all_args(),
+            [
+                "--daemon",
+                "--host",
+                "broker.local",
+                "--morse",
+                "--port",
+                "1884",
+                "--wpm",
+                "12.5"
+            ]
+        );
+        assert_eq!(
+            config.positional().unwrap(),
+            ["lamp/set", r#"{"state":"ON"}"#, r#"{"state":"OFF"}"#]
+        );
+    }
+
+    #[test]
+    fn partial_positional() {
+        let config: Config = "topic = \"lamp/set\"".parse().unwrap();
+        assert!(config.positional().is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("host = ".parse::<Config>().is_err());
+        assert!("[broker]\nhost = \"localhost\"".parse::<Config>().is_err());
+        assert!("topic = true".parse::<Config>().is_err());
+        assert!("profile = [1, 2]".parse::<Config>().is_err());
+    }
+}
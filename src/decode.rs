@@ -0,0 +1,571 @@
+use crate::code::{Code, DecodeCodeError};
+use crate::timing::Signal;
+use itertools::Itertools;
+use std::time::{Duration, Instant};
+
+// Weight given to each new mark when updating the dot and dash estimates.
+const ADAPTATION_RATE: f64 = 0.25;
+
+// Number of runs used to seed the estimates before decoding a complete message. Until then, a
+// mark or gap much shorter than the dot estimate replaces it.
+const SEED_RUNS: usize = 16;
+
+// Shortest dot estimate, in seconds. Anything shorter is a glitch, e.g. a duplicated publish.
+const MIN_UNIT: f64 = 0.01;
+
+// Marks and gaps shorter than this fraction of the dot estimate are glitches. A genuine dot or
+// gap is at least a fifth of a dash, even when keyed sloppily.
+const GLITCH_FRACTION: f64 = 0.15;
+
+/// Classifies mark and gap durations as units of `Signal`, adapting to the speed of the sender.
+///
+/// Running estimates of the dot and dash durations are kept. A mark is classified as whichever is
+/// closer and that estimate is updated, so keying that is not an exact 1:3 ratio or that drifts
+/// over a message is still recovered. Gaps are classified relative to the dot estimate.
+///
+/// Marks and gaps that are too short to have been keyed are glitches, and are ignored.
+#[derive(Clone, Default)]
+pub struct Classifier {
+    dot: Option<f64>,
+    dash: Option<f64>,
+    // Number of marks and gaps observed, up to `SEED_RUNS`.
+    observed: usize,
+}
+
+impl Classifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start with a known dot duration, which is no longer reset by shorter marks or gaps.
+    pub fn with_unit(unit: Duration) -> Self {
+        let dot = unit.as_secs_f64().max(MIN_UNIT);
+        Self {
+            dot: Some(dot),
+            dash: Some(3.0 * dot),
+            observed: SEED_RUNS,
+        }
+    }
+
+    /// Estimated dot duration, if any marks have been observed.
+    pub fn unit(&self) -> Option<Duration> {
+        self.dot.map(Duration::from_secs_f64)
+    }
+
+    /// Whether a mark or gap of `length` is too short to have been keyed.
+    pub fn is_glitch(&self, length: Duration) -> bool {
+        let d = length.as_secs_f64();
+        d < MIN_UNIT || self.dot.map_or(false, |dot| d < GLITCH_FRACTION * dot)
+    }
+
+    /// Update the dot and dash estimates with an observed mark or gap. Glitches are ignored.
+    pub fn update(&mut self, signal: Signal, length: Duration) {
+        if self.is_glitch(length) {
+            return;
+        }
+        let seeding = self.observed < SEED_RUNS;
+        self.observed = SEED_RUNS.min(self.observed + 1);
+
+        let d = length.as_secs_f64();
+        let (dot, dash) = match (self.dot, self.dash, signal) {
+            (Some(dot), Some(dash), _) => (dot, dash),
+            // Assume the first mark is a dot until something shorter is observed.
+            (_, _, Signal::On) => {
+                self.dot = Some(d);
+                self.dash = Some(3.0 * d);
+                return;
+            }
+            (_, _, Signal::Off) => return,
+        };
+
+        let (dot, dash) = match signal {
+            // Something this short means the dot estimate was really a dash. Later on, it is more
+            // likely to be noise, which mustn't throw away the estimate.
+            Signal::On if seeding && d < dot / 2.0 => (d, dot.max(3.0 * d)),
+            Signal::Off if seeding && d < dot / 2.5 => (d, dot.max(3.0 * d)),
+            Signal::On if d < (dot + dash) / 2.0 => (dot + ADAPTATION_RATE * (d - dot), dash),
+            Signal::On => (dot, dash + ADAPTATION_RATE * (d - dash)),
+            Signal::Off => (dot, dash),
+        };
+
+        // Keep dots and dashes distinguishable if only one of them has been seen for a while.
+        self.dot = Some(dot.min(dash / 2.0).max(MIN_UNIT));
+        self.dash = Some(dash.max(2.0 * dot));
+    }
+
+    /// Classify a mark as 1 or 3 units, or a gap as 1, 3 or 7 units.
+    pub fn classify(&self, signal: Signal, length: Duration) -> usize {
+        let d = length.as_secs_f64();
+        let (dot, dash) = match (self.dot, self.dash) {
+            (Some(dot), Some(dash)) => (dot, dash),
+            _ => return 1,
+        };
+
+        match signal {
+            Signal::On if d < (dot + dash) / 2.0 => 1,
+            Signal::On => 3,
+            Signal::Off if d < (dot + dash) / 2.0 => 1,
+            Signal::Off if d < (dash + 7.0 * dot) / 2.0 => 3,
+            Signal::Off => 7,
+        }
+    }
+}
+
+/// Decode a complete message from the durations of its marks and gaps.
+///
+/// Unlike `Code::from_timing`, durations do not need to be exact multiples of a dot. The initial
+/// estimates are seeded from the start of the message and adapt as the message is decoded.
+pub fn decode(
+    durations: impl IntoIterator<Item = (Signal, Duration)>,
+) -> Result<Code, DecodeCodeError> {
+    // Merge consecutive durations of the same signal.
+    let runs: Vec<_> = durations
+        .into_iter()
+        .coalesce(|(a, x), (b, y)| {
+            if a == b {
+                Ok((a, x + y))
+            } else {
+                Err(((a, x), (b, y)))
+            }
+        })
+        .collect();
+
+    let mut classifier = Classifier::new();
+    for &(signal, length) in runs.iter().take(SEED_RUNS) {
+        classifier.update(signal, length);
+    }
+    let mut classifier = classifier
+        .unit()
+        .map_or_else(Classifier::new, Classifier::with_unit);
+
+    // Drop glitches, joining the runs either side of them.
+    let glitches = classifier.clone();
+    let runs: Vec<_> = runs
+        .into_iter()
+        .filter(|&(_, length)| !glitches.is_glitch(length))
+        .coalesce(|(a, x), (b, y)| {
+            if a == b {
+                Ok((a, x + y))
+            } else {
+                Err(((a, x), (b, y)))
+            }
+        })
+        .collect();
+
+    let timing = runs
+        .into_iter()
+        .flat_map(|(signal, length)| {
+            classifier.update(signal, length);
+            std::iter::repeat(signal).take(classifier.classify(signal, length))
+        })
+        .collect::<Vec<_>>();
+
+    Code::from_timing(timing)
+}
+
+/// Decodes Morse code from timestamped key transitions, such as those observed on an MQTT topic.
+///
+/// The dot duration is not known ahead of time and is tracked with a `Classifier`.
+#[derive(Default)]
+pub struct Receiver {
+    // Current signal and when it started.
+    state: Option<(Signal, Instant)>,
+    // When the run before the current one started, whether it is still the last of `runs`, and
+    // the classifier from before it was observed.
+    previous: Option<(Instant, bool, Classifier)>,
+    // Completed marks and mark gaps of the letter being received.
+    runs: Vec<(Signal, Duration)>,
+    classifier: Classifier,
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Estimated dot duration, if any marks have been observed.
+    pub fn unit(&self) -> Option<Duration> {
+        self.classifier.unit()
+    }
+
+    /// Record a transition to `signal` at time `at`.
+    ///
+    /// Returns the text of a letter once the gap following it is known to be a letter or word
+    /// gap. A word gap is returned as a trailing space.
+    pub fn push(&mut self, signal: Signal, at: Instant) -> Option<Result<String, DecodeCodeError>> {
+        let (previous, start) = match self.state {
+            Some((previous, _)) if previous == signal => return None,
+            Some(state) => state,
+            None => {
+                // Ignore any gap before the first mark.
+                if signal == Signal::On {
+                    self.state = Some((signal, at));
+                }
+                return None;
+            }
+        };
+
+        let length = at.duration_since(start);
+        if self.classifier.is_glitch(length) {
+            match self.previous.take() {
+                // Carry on with the run before the glitch as if it never ended.
+                Some((earlier, in_runs, classifier)) => {
+                    if in_runs {
+                        self.runs.pop();
+                    }
+                    self.state = Some((signal, earlier));
+                    self.classifier = classifier;
+                    return None;
+                }
+                // Wait for a mark that lasts.
+                None if previous == Signal::On && self.runs.is_empty() => {
+                    self.state = None;
+                    return None;
+                }
+                // Two glitches in a row are taken as they come.
+                None => {}
+            }
+        }
+
+        self.state = Some((signal, at));
+        self.previous = Some((start, true, self.classifier.clone()));
+        self.classifier.update(previous, length);
+
+        self.runs.push((previous, length));
+        if previous == Signal::On {
+            return None;
+        }
+
+        match self.classifier.classify(Signal::Off, length) {
+            1 => None,
+            units => {
+                self.runs.pop();
+                if let Some((_, in_runs, _)) = self.previous.as_mut() {
+                    *in_runs = false;
+                }
+                Some(self.take_letter().map(|mut text| {
+                    if units == 7 {
+                        text.push(' ');
+                    }
+                    text
+                }))
+            }
+        }
+    }
+
+    /// Complete the letter being received if the key has been up for at least a word gap.
+    ///
+    /// This should be called periodically as the end of a transmission is not followed by
+    /// another transition.
+    pub fn flush(&mut self, now: Instant) -> Option<Result<String, DecodeCodeError>> {
+        match self.state {
+            Some((Signal::Off, start))
+                if self
+                    .classifier
+                    .classify(Signal::Off, now.duration_since(start))
+                    == 7 =>
+            {
+                self.state = None;
+                self.previous = None;
+                Some(self.take_letter())
+            }
+            _ => None,
+        }
+    }
+
+    fn take_letter(&mut self) -> Result<String, DecodeCodeError> {
+        let runs = std::mem::take(&mut self.runs);
+        let timing = runs
+            .into_iter()
+            .flat_map(|(signal, length)| {
+                std::iter::repeat(signal).take(self.classifier.classify(signal, length))
+            })
+            .collect::<Vec<_>>();
+
+        Code::from_timing(timing).map(|c| c.text())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, Classifier, Receiver};
+    use crate::code::Code;
+    use crate::timing::Signal;
+    use itertools::Itertools;
+    use std::convert::TryFrom;
+    use std::time::{Duration, Instant};
+
+    // Transitions for `code` sent with a fixed dot duration.
+    fn transitions(code: &Code, unit: Duration, start: Instant) -> Vec<(Signal, Instant)> {
+        let mut at = start;
+        let mut ts = Vec::new();
+        for (signal, group) in &code.timing().group_by(|x| *x) {
+            ts.push((signal, at));
+            at += unit * u32::try_from(group.count()).unwrap();
+        }
+        ts.push((Signal::Off, at));
+        ts
+    }
+
+    fn receive(text: &str, unit: Duration) -> String {
+        let start = Instant::now();
+        let mut r = Receiver::new();
+        let mut received = String::new();
+
+        let ts = transitions(&text.parse().unwrap(), unit, start);
+        let end = ts.last().unwrap().1;
+
+        for (signal, at) in ts {
+            if let Some(text) = r.push(signal, at) {
+                received.push_str(&text.unwrap());
+            }
+        }
+        assert!(r.flush(end + unit).is_none());
+        received.push_str(&r.flush(end + unit * 7).unwrap().unwrap());
+
+        received
+    }
+
+    #[test]
+    fn receive_phrase() {
+        assert_eq!(
+            receive("MORSE CODE", Duration::from_millis(50)),
+            "MORSE CODE"
+        );
+    }
+
+    #[test]
+    fn receive_dashes() {
+        // There is no dot to compare against, so the unit comes from the gaps between dashes.
+        // Without any, e.g. "TT", the timing is the same as "I" sent three times slower.
+        assert_eq!(receive("OTTO", Duration::from_millis(50)), "OTTO");
+        assert_eq!(receive("MOM TO", Duration::from_millis(50)), "MOM TO");
+    }
+
+    #[test]
+    fn estimate_unit() {
+        let start = Instant::now();
+        let mut r = Receiver::new();
+        assert_eq!(r.unit(), None);
+
+        for (signal, at) in transitions(&"K".parse().unwrap(), Duration::from_millis(20), start) {
+            r.push(signal, at);
+        }
+        assert_eq!(r.unit(), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn repeated_signal() {
+        let start = Instant::now();
+        let unit = Duration::from_millis(10);
+        let mut r = Receiver::new();
+
+        assert!(r.push(Signal::Off, start).is_none());
+        assert!(r.push(Signal::On, start).is_none());
+        assert!(r.push(Signal::On, start + unit).is_none());
+        assert!(r.push(Signal::Off, start + unit).is_none());
+        assert_eq!(r.flush(start + unit * 8).unwrap().unwrap(), "E".to_string());
+        assert!(r.flush(start + unit * 9).is_none());
+    }
+
+    #[test]
+    fn unknown_letter() {
+        let start = Instant::now();
+        let unit = Duration::from_millis(10);
+        let mut r = Receiver::new();
+
+        for i in 0..9 {
+            r.push(Signal::On, start + unit * (2 * i));
+            r.push(Signal::Off, start + unit * (2 * i + 1));
+        }
+        assert!(r.flush(start + unit * 30).unwrap().is_err());
+    }
+
+    // Durations for `code` as keyed by a sloppy operator: each element is stretched by up to 20%
+    // and the dot duration drifts linearly from `first` to `last` over the message.
+    fn sloppy(code: &Code, first: Duration, last: Duration) -> Vec<(Signal, Duration)> {
+        let groups = code
+            .timing()
+            .group_by(|x| *x)
+            .into_iter()
+            .map(|(signal, group)| (signal, group.count()))
+            .collect::<Vec<_>>();
+
+        let mut seed = 7_u32;
+        let n = groups.len() as f64;
+        groups
+            .into_iter()
+            .enumerate()
+            .map(|(i, (signal, units))| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let jitter = 0.8 + 0.4 * f64::from(seed >> 16 & 0xff) / 255.0;
+                let t = i as f64 / n;
+                let unit = first.as_secs_f64() * (1.0 - t) + last.as_secs_f64() * t;
+                (
+                    signal,
+                    Duration::from_secs_f64(unit * units as f64 * jitter),
+                )
+            })
+            .collect()
+    }
+
+    // `durations` with a glitch of `length` in the middle of each of the runs at `indices`, which
+    // are in descending order.
+    fn with_glitches(
+        mut durations: Vec<(Signal, Duration)>,
+        indices: &[usize],
+        length: Duration,
+    ) -> Vec<(Signal, Duration)> {
+        for &i in indices {
+            let (signal, d) = durations[i];
+            let other = match signal {
+                Signal::On => Signal::Off,
+                Signal::Off => Signal::On,
+            };
+            let half = d / 2;
+            durations.splice(
+                i..=i,
+                vec![(signal, half), (other, length), (signal, d - half - length)],
+            );
+        }
+        durations
+    }
+
+    fn receive_durations(durations: Vec<(Signal, Duration)>) -> String {
+        let start = Instant::now();
+        let mut r = Receiver::new();
+        let mut received = String::new();
+
+        let mut at = start;
+        for (signal, length) in durations {
+            if let Some(text) = r.push(signal, at) {
+                received.push_str(&text.unwrap());
+            }
+            at += length;
+        }
+        r.push(Signal::Off, at);
+        received.push_str(&r.flush(at + Duration::from_secs(1)).unwrap().unwrap());
+
+        received
+    }
+
+    #[test]
+    fn classify_exact() {
+        let unit = Duration::from_millis(50);
+        let c = Classifier::with_unit(unit);
+        assert_eq!(c.classify(Signal::On, unit), 1);
+        assert_eq!(c.classify(Signal::On, unit * 3), 3);
+        assert_eq!(c.classify(Signal::Off, unit), 1);
+        assert_eq!(c.classify(Signal::Off, unit * 3), 3);
+        assert_eq!(c.classify(Signal::Off, unit * 7), 7);
+    }
+
+    #[test]
+    fn classify_heavy_dash() {
+        let mut c = Classifier::with_unit(Duration::from_millis(50));
+        for _ in 0..10 {
+            c.update(Signal::On, Duration::from_millis(50));
+            c.update(Signal::On, Duration::from_millis(250));
+        }
+        assert_eq!(c.classify(Signal::On, Duration::from_millis(130)), 1);
+        assert_eq!(c.classify(Signal::On, Duration::from_millis(250)), 3);
+    }
+
+    #[test]
+    fn classifier_seeded_with_dash() {
+        let mut c = Classifier::new();
+        c.update(Signal::On, Duration::from_millis(150));
+        assert_eq!(c.unit(), Some(Duration::from_millis(150)));
+
+        c.update(Signal::Off, Duration::from_millis(50));
+        assert_eq!(c.unit(), Some(Duration::from_millis(50)));
+        assert_eq!(c.classify(Signal::On, Duration::from_millis(150)), 3);
+    }
+
+    #[test]
+    fn classifier_keeps_estimate() {
+        let mut c = Classifier::with_unit(Duration::from_millis(50));
+        c.update(Signal::On, Duration::from_micros(200));
+        assert_eq!(c.unit(), Some(Duration::from_millis(50)));
+
+        // Past the seed window, a short mark is a sloppy dot, not a sign the unit was a dash.
+        c.update(Signal::On, Duration::from_millis(20));
+        assert!(c.unit().unwrap() > Duration::from_millis(40));
+
+        assert_eq!(
+            Classifier::with_unit(Duration::from_micros(10)).unit(),
+            Some(Duration::from_millis(10))
+        );
+    }
+
+    #[test]
+    fn decode_exact() {
+        let code: Code = "MORSE CODE".parse().unwrap();
+        let unit = Duration::from_millis(50);
+        let durations = code
+            .timing()
+            .map(|signal| (signal, unit))
+            .collect::<Vec<_>>();
+        assert_eq!(decode(durations).unwrap(), code);
+    }
+
+    #[test]
+    fn decode_sloppy() {
+        let code: Code = "THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG 1234567890"
+            .parse()
+            .unwrap();
+        let durations = sloppy(&code, Duration::from_millis(60), Duration::from_millis(35));
+        assert_eq!(decode(durations).unwrap().text(), code.text());
+    }
+
+    #[test]
+    fn decode_slowing_down() {
+        let code: Code = "PARIS PARIS PARIS PARIS".parse().unwrap();
+        let durations = sloppy(&code, Duration::from_millis(40), Duration::from_millis(80));
+        assert_eq!(decode(durations).unwrap().text(), code.text());
+    }
+
+    #[test]
+    fn decode_dash_first() {
+        let code: Code = "OTTO".parse().unwrap();
+        let durations = sloppy(&code, Duration::from_millis(50), Duration::from_millis(50));
+        assert_eq!(decode(durations).unwrap().text(), "OTTO");
+    }
+
+    #[test]
+    fn decode_glitches() {
+        let code: Code = "PARIS PARIS PARIS".parse().unwrap();
+        let durations = sloppy(&code, Duration::from_millis(50), Duration::from_millis(50));
+
+        // Short marks in gaps, as from a duplicated publish, and short gaps in marks.
+        let glitched = with_glitches(durations.clone(), &[45, 1], Duration::from_micros(200));
+        assert_eq!(decode(glitched).unwrap().text(), code.text());
+        let glitched = with_glitches(durations, &[30, 0], Duration::from_millis(5));
+        assert_eq!(decode(glitched).unwrap().text(), code.text());
+    }
+
+    #[test]
+    fn receive_sloppy() {
+        let code: Code = "CQ CQ DE MORSEQTT".parse().unwrap();
+        let durations = sloppy(&code, Duration::from_millis(50), Duration::from_millis(40));
+        assert_eq!(receive_durations(durations), code.text());
+    }
+
+    #[test]
+    fn receive_glitches() {
+        let code: Code = "PARIS PARIS PARIS".parse().unwrap();
+        let durations = sloppy(&code, Duration::from_millis(50), Duration::from_millis(50));
+
+        let glitched = with_glitches(durations.clone(), &[45, 9, 1], Duration::from_micros(200));
+        assert_eq!(receive_durations(glitched), code.text());
+        let glitched = with_glitches(durations, &[30, 0], Duration::from_millis(5));
+        assert_eq!(receive_durations(glitched), code.text());
+
+        // A glitch before the first mark is ignored.
+        let start = Instant::now();
+        let mut r = Receiver::new();
+        r.push(Signal::On, start);
+        r.push(Signal::Off, start + Duration::from_micros(200));
+        assert_eq!(r.unit(), None);
+    }
+}
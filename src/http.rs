@@ -0,0 +1,512 @@
+use crate::code::Code;
+use crate::json::write_json_string;
+use crate::queue::{Job, Priority, Queue};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Longest wait for a client to send or receive data.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Largest request line and headers accepted, together.
+const MAX_HEAD: u64 = 8 * 1024;
+
+// Largest request body accepted.
+const MAX_BODY: usize = 64 * 1024;
+
+// Most connections handled at once. Any more are turned away.
+const MAX_CONNECTIONS: usize = 16;
+
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: Vec<u8>,
+}
+
+impl Request {
+    // The value of query parameter `name`, which is empty for a bare `?name`.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .map(|p| p.split_once('=').unwrap_or((p, "")))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, body: impl fmt::Display) -> Self {
+        Self {
+            status,
+            body: body.to_string(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::new(status, JsonError(message))
+    }
+
+    fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            503 => "Service Unavailable",
+            _ => "",
+        };
+
+        write!(
+            w,
+            concat!(
+                "HTTP/1.1 {} {}\r\n",
+                "Content-Type: application/json\r\n",
+                "Content-Length: {}\r\n",
+                "Connection: close\r\n\r\n",
+                "{}"
+            ),
+            self.status,
+            reason,
+            self.body.len() + 1,
+            self.body
+        )?;
+        writeln!(w)?;
+        w.flush()
+    }
+}
+
+struct JsonError<'a>(&'a str);
+
+impl fmt::Display for JsonError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{\"error\":")?;
+        write_json_string(f, self.0)?;
+        write!(f, "}}")
+    }
+}
+
+struct JsonJobs<'a>(&'a [Job]);
+
+impl fmt::Display for JsonJobs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, job) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{{\"id\":{},\"text\":", job.id())?;
+            write_json_string(f, job.text())?;
+            write!(f, ",\"priority\":\"{}\"}}", job.priority())?;
+        }
+        write!(f, "]")
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn too_large(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+// Read a line of the request line or headers, which must fit in what is left of `head`.
+fn read_head_line(head: &mut io::Take<impl BufRead>, line: &mut String) -> io::Result<usize> {
+    let read = head.read_line(line)?;
+    if read > 0 && !line.ends_with('\n') && head.limit() == 0 {
+        return Err(too_large("Request headers too large"));
+    }
+    Ok(read)
+}
+
+// Read a request, ignoring every header but Content-Length.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let mut head = reader.take(MAX_HEAD);
+    let mut line = String::new();
+    read_head_line(&mut head, &mut line)?;
+
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), target)
+        }
+        _ => return Err(invalid_data("Invalid request line")),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if read_head_line(&mut head, &mut line)? == 0 {
+            return Err(invalid_data("Incomplete headers"));
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_data("Invalid Content-Length"))?;
+            }
+        }
+    }
+
+    if length > MAX_BODY {
+        return Err(too_large("Request body too large"));
+    }
+    let mut body = vec![0; length];
+    head.into_inner().read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    })
+}
+
+fn submit(queue: &Queue, request: &Request, morse: bool) -> Response {
+    let priority = match request.param("priority").map(Priority::from_str) {
+        Some(Ok(priority)) => priority,
+        Some(Err(e)) => return Response::error(400, e),
+        None => Priority::Normal,
+    };
+
+    let text = match std::str::from_utf8(&request.body) {
+        Ok(text) => text.trim(),
+        Err(_) => return Response::error(400, "Message is not valid UTF-8"),
+    };
+    let morse = match request.param("morse") {
+        Some("") | Some("1") | Some("true") => true,
+        Some("0") | Some("false") => false,
+        Some(_) => return Response::error(400, "Invalid value for 'morse'"),
+        None => morse,
+    };
+
+    let code = if morse {
+        Code::from_morse(text).map_err(|e| e.to_string())
+    } else {
+        Code::from_str(text).map_err(|_| "Message contained invalid characters".to_string())
+    };
+
+    match code {
+        Ok(code) if code.is_empty() => Response::error(400, "Message is empty"),
+        Ok(code) => {
+            let id = queue.push(&code, priority, priority == Priority::Emergency);
+            Response::new(202, format_args!("{{\"id\":{}}}", id))
+        }
+        Err(e) => Response::error(400, &e),
+    }
+}
+
+fn cancelled(cancelled: Option<u64>) -> Response {
+    match cancelled {
+        Some(id) => Response::new(200, format_args!("{{\"cancelled\":{}}}", id)),
+        None => Response::error(404, "No such message"),
+    }
+}
+
+fn respond(queue: &Queue, request: &Request, morse: bool) -> Response {
+    let method = request.method.as_str();
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["status"]) => Response::new(200, queue.status()),
+        ("GET", ["messages"]) => Response::new(200, JsonJobs(&queue.waiting())),
+        ("POST", ["messages"]) => submit(queue, request, morse),
+        ("POST", ["cancel"]) => cancelled(
+            queue
+                .current()
+                .map(|job| job.id())
+                .filter(|id| queue.cancel(*id)),
+        ),
+        ("DELETE", ["messages", id]) => cancelled(id.parse().ok().filter(|id| queue.cancel(*id))),
+        (_, ["status"]) | (_, ["messages"]) | (_, ["cancel"]) | (_, ["messages", _]) => {
+            Response::error(405, "Method not allowed")
+        }
+        _ => Response::error(404, "Not found"),
+    }
+}
+
+fn handle(mut stream: TcpStream, queue: &Queue, morse: bool) -> io::Result<()> {
+    // Connections are handled on threads of their own, so these only bound how long a stalled
+    // client keeps its thread.
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => respond(queue, &request, morse),
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+            Response::error(400, &e.to_string())
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => {
+            Response::error(413, &e.to_string())
+        }
+        Err(e) => return Err(e),
+    };
+
+    response.write_to(&mut stream)
+}
+
+/// Serve an HTTP API for `queue`, handling each connection on a thread of its own, until
+/// `listener` fails. Connections beyond the first 16 at a time are turned away.
+///
+/// * `GET /status` returns the queue's [`Status`](crate::queue::Status).
+/// * `GET /messages` lists the messages waiting.
+/// * `POST /messages` queues the text in the request body and returns its id. The priority may
+///   be set with `?priority=<priority>`, and dot/dash notation is accepted with `?morse` (or
+///   rejected with `?morse=false`).
+/// * `DELETE /messages/<id>` cancels a message.
+/// * `POST /cancel` cancels the message being sent.
+///
+/// With `morse`, all messages are dot/dash notation.
+pub fn serve(listener: TcpListener, queue: Queue, morse: bool) -> io::Result<()> {
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = stream
+                .set_write_timeout(Some(TIMEOUT))
+                .and_then(|_| Response::error(503, "Too many connections").write_to(&mut stream));
+            continue;
+        }
+
+        let connection = Connection(Arc::clone(&connections));
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            // A failed connection only affects that client.
+            let _ = handle(stream, &queue, morse);
+            drop(connection);
+        });
+    }
+
+    Ok(())
+}
+
+// Counts a connection being handled until dropped.
+struct Connection(Arc<AtomicUsize>);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_request, respond, serve, Request, Response, MAX_CONNECTIONS};
+    use crate::code::Code;
+    use crate::queue::{Priority, Queue};
+    use std::io::{self, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    fn request(method: &str, target: &str, body: &str) -> Request {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn parse_request() {
+        let raw = concat!(
+            "POST /messages?priority=high HTTP/1.1\r\n",
+            "Host: localhost\r\n",
+            "content-length: 3\r\n\r\n",
+            "SOS"
+        );
+
+        assert_eq!(
+            read_request(&mut BufReader::new(raw.as_bytes())).unwrap(),
+            request("POST", "/messages?priority=high", "SOS")
+        );
+        assert!(read_request(&mut BufReader::new("GET /status\r\n\r\n".as_bytes())).is_err());
+        assert!(read_request(&mut BufReader::new("GET /status HTTP/1.1\r\n".as_bytes())).is_err());
+
+        let long_header = format!("GET /status HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(10_000));
+        assert_eq!(
+            read_request(&mut BufReader::new(long_header.as_bytes()))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+        let long_target = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10_000));
+        assert_eq!(
+            read_request(&mut BufReader::new(long_target.as_bytes()))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn submit() {
+        let queue = Queue::new();
+
+        assert_eq!(
+            respond(&queue, &request("POST", "/messages", "sos\n"), false),
+            Response::new(202, r#"{"id":0}"#)
+        );
+        assert_eq!(
+            respond(
+                &queue,
+                &request("POST", "/messages?priority=high&morse", "... ---"),
+                false
+            ),
+            Response::new(202, r#"{"id":1}"#)
+        );
+        assert_eq!(
+            respond(&queue, &request("GET", "/messages", ""), false),
+            Response::new(
+                200,
+                r#"[{"id":1,"text":"SO","priority":"high"},{"id":0,"text":"SOS","priority":"normal"}]"#
+            )
+        );
+
+        assert_eq!(
+            respond(
+                &queue,
+                &request("POST", "/messages?priority=urgent", "SOS"),
+                false
+            )
+            .status,
+            400
+        );
+        assert_eq!(
+            respond(&queue, &request("POST", "/messages", " "), false).status,
+            400
+        );
+        assert_eq!(
+            respond(&queue, &request("POST", "/messages", "SOS"), true).status,
+            400
+        );
+        assert_eq!(
+            respond(
+                &queue,
+                &request("POST", "/messages?morse=false", "SOS"),
+                true
+            ),
+            Response::new(202, r#"{"id":2}"#)
+        );
+        assert_eq!(
+            respond(
+                &queue,
+                &request("POST", "/messages?morse=yes", "SOS"),
+                false
+            )
+            .status,
+            400
+        );
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn cancel() {
+        let queue = Queue::new();
+        let id = queue.push(&Code::from_str("SOS").unwrap(), Priority::Normal, false);
+
+        assert_eq!(
+            respond(&queue, &request("POST", "/cancel", ""), false).status,
+            404
+        );
+        assert_eq!(
+            respond(
+                &queue,
+                &request("DELETE", &format!("/messages/{}", id), ""),
+                false
+            ),
+            Response::new(200, format!("{{\"cancelled\":{}}}", id))
+        );
+        assert_eq!(
+            respond(
+                &queue,
+                &request("DELETE", &format!("/messages/{}", id), ""),
+                false
+            )
+            .status,
+            404
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn not_found() {
+        let queue = Queue::new();
+
+        assert_eq!(respond(&queue, &request("GET", "/", ""), false).status, 404);
+        assert_eq!(
+            respond(&queue, &request("PUT", "/status", ""), false).status,
+            405
+        );
+    }
+
+    #[test]
+    fn serve_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener, Queue::new(), false));
+
+        // A client that never sends its request doesn't hold up the others.
+        let _stalled = TcpStream::connect(address).unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        write!(stream, "GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(
+            response.ends_with("\r\n\r\n{\"state\":\"idle\",\"queued\":0,\"last_error\":null}\n")
+        );
+    }
+
+    #[test]
+    fn serve_too_many_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener, Queue::new(), false));
+
+        let _stalled: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    }
+}
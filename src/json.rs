@@ -0,0 +1,48 @@
+use std::fmt;
+
+// Write `s` as the contents of a JSON string, without the quotes.
+pub(crate) fn write_json_escaped(f: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", u32::from(c))?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+// Write `s` as a JSON string.
+pub(crate) fn write_json_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    write_json_escaped(f, s)?;
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_json_escaped, write_json_string};
+    use std::fmt;
+
+    struct Json(&'static str);
+
+    impl fmt::Display for Json {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write_json_string(f, self.0)
+        }
+    }
+
+    #[test]
+    fn escaped() {
+        let mut s = String::new();
+        write_json_escaped(&mut s, "<AR> \"+\"\\\t").unwrap();
+        assert_eq!(s, r#"<AR> \"+\"\\\u0009"#);
+    }
+
+    #[test]
+    fn string() {
+        assert_eq!(Json("").to_string(), r#""""#);
+        assert_eq!(Json("SOS\n").to_string(), r#""SOS\u000a""#);
+    }
+}
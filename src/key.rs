@@ -1,54 +1,733 @@
-use crate::timing::Signal;
+use crate::clock::Clock;
+use crate::code::Code;
+use crate::template::Template;
+use crate::timing::{Signal, Speed, Symbol};
+use futures::sync::oneshot;
 use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
-use rumqtt::{MqttClient, QoS};
-use std::convert::{TryFrom, TryInto};
+use rumqtt::{
+    ClientError, ConnectError, LastWill, MqttClient, MqttOptions, Notification, QoS, Receiver,
+    ReconnectOptions,
+};
+use std::convert::TryInto;
+use std::error;
+use std::fmt;
 use std::ops::DerefMut;
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
 use tokio::prelude::*;
 use tokio::timer::Delay;
 
+/// Reason a transmission did not complete.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransmitError {
+    /// The key could not publish a transition.
+    Publish { reason: String },
+    /// The connection to the broker is gone.
+    Disconnected,
+    /// The transmission was stopped before it completed.
+    Cancelled,
+}
+
+impl fmt::Display for TransmitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Publish { reason } => write!(f, "Publish failed: {}", reason),
+            Self::Disconnected => write!(f, "Disconnected from MQTT broker"),
+            Self::Cancelled => write!(f, "Transmission cancelled"),
+        }
+    }
+}
+
+impl error::Error for TransmitError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl From<ClientError> for TransmitError {
+    fn from(e: ClientError) -> Self {
+        match e {
+            // The connection thread has exited.
+            ClientError::MpscRequestSend(_) | ClientError::MpscCommandSend(_) => Self::Disconnected,
+            e => Self::Publish {
+                reason: e.to_string(),
+            },
+        }
+    }
+}
+
+/// Kind of mark or gap being sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ElementKind {
+    Dot,
+    Dash,
+    Gap,
+}
+
+impl fmt::Display for ElementKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Dot => "dot",
+                Self::Dash => "dash",
+                Self::Gap => "gap",
+            }
+        )
+    }
+}
+
+/// A mark or gap at the point it is sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Element {
+    pub kind: ElementKind,
+    pub duration: Duration,
+    /// The letter the element is part of, decoded from the symbols. Gaps after a letter are part
+    /// of it, except for word gaps, which are a space. Prosigns are written as `<SK>`, also when
+    /// their code is shared with a character, e.g. `<AR>` for `+`.
+    pub character: String,
+}
+
+impl Element {
+    /// An element of no particular length or letter, e.g. to release the key after a message.
+    /// Its duration is 0 and its character empty.
+    pub fn untimed(signal: Signal) -> Self {
+        Self {
+            kind: match signal {
+                Signal::On => ElementKind::Dot,
+                Signal::Off => ElementKind::Gap,
+            },
+            duration: Duration::from_secs(0),
+            character: String::new(),
+        }
+    }
+
+    pub fn signal(&self) -> Signal {
+        match self.kind {
+            ElementKind::Dot | ElementKind::Dash => Signal::On,
+            ElementKind::Gap => Signal::Off,
+        }
+    }
+}
+
+/// An output that can be switched on and off to transmit Morse code.
+pub trait Key {
+    fn key_down(&mut self) -> Result<(), TransmitError>;
+
+    fn key_up(&mut self) -> Result<(), TransmitError>;
+
+    /// Start sending `element`. Keys that only switch on and off needn't implement this.
+    fn send(&mut self, element: &Element) -> Result<(), TransmitError> {
+        match element.signal() {
+            Signal::On => self.key_down(),
+            Signal::Off => self.key_up(),
+        }
+    }
+
+    /// The final key up of a transmission, which is left as the key's state.
+    fn release(&mut self) -> Result<(), TransmitError> {
+        self.key_up()
+    }
+
+    /// Called after the final key up of a transmission.
+    fn flush(&mut self) -> Result<(), TransmitError> {
+        Ok(())
+    }
+}
+
+/// Delays between reconnection attempts, doubling from `initial` up to `max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Delay before reconnection attempt `attempt`, counting from 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        2_u32
+            .checked_pow(attempt)
+            .and_then(|factor| self.initial.checked_mul(factor))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+        }
+    }
+}
+
+/// What to do with a message that was interrupted by a disconnection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InFlight {
+    /// Transmit the message again from the start once reconnected.
+    Retransmit,
+    /// Drop the message and report the error.
+    Abort,
+}
+
+impl std::str::FromStr for InFlight {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "retransmit" => Ok(Self::Retransmit),
+            "abort" => Ok(Self::Abort),
+            _ => Err("expected 'retransmit' or 'abort'"),
+        }
+    }
+}
+
+/// Which publishes the broker should retain for late subscribers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Retain {
+    Never,
+    Always,
+    /// Only the key up at the end of a transmission.
+    FinalOff,
+}
+
+impl std::str::FromStr for Retain {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "never" => Ok(Self::Never),
+            "always" => Ok(Self::Always),
+            "final-off" => Ok(Self::FinalOff),
+            _ => Err("expected 'never', 'always' or 'final-off'"),
+        }
+    }
+}
+
+impl Retain {
+    /// Whether a publish is retained, where `release` is the final key up of a transmission.
+    pub fn retains(self, release: bool) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Always => true,
+            Self::FinalOff => release,
+        }
+    }
+}
+
+// Longest time to wait for the broker to acknowledge the final publishes when disconnecting.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A client and its notifications, which tell when the broker has received publishes.
+struct Publisher {
+    client: MqttClient,
+    notifications: Option<Receiver<Notification>>,
+    // QoS 1 and 2 publishes the broker hasn't acknowledged yet.
+    unacked: usize,
+}
+
+impl Publisher {
+    #[allow(clippy::result_large_err)]
+    fn start(options: MqttOptions) -> Result<Self, ConnectError> {
+        let (client, notifications) = MqttClient::start(options)?;
+        Ok(Self {
+            client,
+            notifications: Some(notifications),
+            unacked: 0,
+        })
+    }
+
+    // Count the acknowledgements received so far. Notifications are dropped once the channel is
+    // full, so this is done before every publish.
+    fn acknowledged(&mut self) {
+        if let Some(notifications) = &self.notifications {
+            for notification in notifications.try_iter() {
+                if let Notification::PubAck(_) | Notification::PubComp(_) = notification {
+                    self.unacked = self.unacked.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    fn publish(
+        &mut self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(), TransmitError> {
+        self.acknowledged();
+        self.client.publish(topic, qos, retain, payload)?;
+        if qos != QoS::AtMostOnce {
+            self.unacked += 1;
+        }
+
+        Ok(())
+    }
+
+    // Wait until the broker has acknowledged every publish, or until `deadline`. Returns false if
+    // it didn't in time. A client without notifications can't tell, and doesn't wait.
+    fn wait_for_acks(&mut self, deadline: Instant) -> bool {
+        let notifications = match &self.notifications {
+            Some(notifications) => notifications,
+            None => return true,
+        };
+
+        while self.unacked > 0 {
+            match notifications.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Notification::PubAck(_)) | Ok(Notification::PubComp(_)) => self.unacked -= 1,
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+
+    // Close the connection once the final publishes have been acknowledged.
+    fn disconnect(mut self, deadline: Instant) -> Result<(), TransmitError> {
+        let acknowledged = self.wait_for_acks(deadline);
+        let _ = self.client.pause();
+
+        if acknowledged {
+            Ok(())
+        } else {
+            Err(TransmitError::Publish {
+                reason: "The broker did not acknowledge the final publishes".to_string(),
+            })
+        }
+    }
+}
+
+/// A new connection for an [`MqttKey`], made by its connector.
+pub struct MqttConnection {
+    key: Publisher,
+    availability: Option<Publisher>,
+}
+
+// Online/offline status on a separate connection, as the key's last will is used to release the
+// key.
+struct Availability {
+    client: Option<Publisher>,
+    options: MqttOptions,
+    topic: String,
+    online: String,
+    offline: String,
+}
+
+impl Availability {
+    // Connect and publish the online status. This blocks until the broker accepts the connection.
+    #[allow(clippy::result_large_err)]
+    fn start(options: MqttOptions, topic: &str, online: &str) -> Result<Publisher, ConnectError> {
+        let mut client = Publisher::start(options)?;
+        // A failure here shows up as a failure of the key soon enough.
+        let _ = client.publish(topic, QoS::AtLeastOnce, true, online);
+
+        Ok(client)
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn connect(&mut self) -> Result<(), ConnectError> {
+        self.client = Some(Self::start(
+            self.options.clone(),
+            &self.topic,
+            &self.online,
+        )?);
+
+        Ok(())
+    }
+
+    fn go_offline(&mut self) -> Result<(), TransmitError> {
+        let client = self.client.as_mut().ok_or(TransmitError::Disconnected)?;
+        client.publish(
+            self.topic.as_str(),
+            QoS::AtLeastOnce,
+            true,
+            self.offline.as_str(),
+        )?;
+        Ok(())
+    }
+}
+
+/// A key that publishes its payloads to an MQTT topic. The payloads are [`Template`]s.
 #[allow(clippy::module_name_repetitions)]
 pub struct MqttKey {
-    client: MqttClient,
+    client: Option<Publisher>,
+    options: Option<MqttOptions>,
+    availability: Option<Availability>,
     topic: String,
-    on_payload: String,
-    off_payload: String,
-    progress: Option<ProgressBar>,
+    on_payload: Template,
+    off_payload: Template,
+    qos: QoS,
+    retain: Retain,
+    // Number of payloads published.
+    sequence: u64,
 }
 
 impl MqttKey {
-    pub fn new(client: MqttClient, topic: String, on_payload: String, off_payload: String) -> Self {
+    pub fn new(
+        client: MqttClient,
+        topic: String,
+        on_payload: String,
+        off_payload: String,
+        qos: QoS,
+        retain: Retain,
+    ) -> Self {
         Self {
-            client,
+            client: Some(Publisher {
+                client,
+                notifications: None,
+                unacked: 0,
+            }),
+            options: None,
+            availability: None,
+            topic,
+            on_payload: Template::new(&on_payload),
+            off_payload: Template::new(&off_payload),
+            qos,
+            retain,
+            sequence: 0,
+        }
+    }
+
+    /// Connect to the broker described by `options`, keeping them so the key can [`reconnect`].
+    ///
+    /// The broker is asked to publish `off_payload` if the connection is lost, so that the key is
+    /// not left down. As a last will, it is rendered for an untimed gap.
+    #[allow(clippy::result_large_err)]
+    pub fn connect(
+        options: MqttOptions,
+        topic: String,
+        on_payload: String,
+        off_payload: String,
+        qos: QoS,
+        retain: Retain,
+    ) -> Result<Self, ConnectError> {
+        let on_payload = Template::new(&on_payload);
+        let off_payload = Template::new(&off_payload);
+
+        // The client would otherwise reconnect by itself and replay transitions queued while the
+        // broker was away, long after they were due.
+        let options = options
+            .set_reconnect_opts(ReconnectOptions::Never)
+            .set_last_will(LastWill {
+                topic: topic.clone(),
+                message: off_payload.render(&Element::untimed(Signal::Off), 0),
+                qos,
+                retain: retain != Retain::Never,
+            });
+        let client = Publisher::start(options.clone())?;
+
+        Ok(Self {
+            client: Some(client),
+            options: Some(options),
+            availability: None,
             topic,
             on_payload,
             off_payload,
-            progress: None,
+            qos,
+            retain,
+            sequence: 0,
+        })
+    }
+
+    /// Publish `online` to `topic` now and after each reconnection, using a second connection
+    /// described by `options` with `offline` as its last will. Both are retained.
+    ///
+    /// `options` needs a different client id from the key's.
+    #[allow(clippy::result_large_err)]
+    pub fn with_availability(
+        mut self,
+        options: MqttOptions,
+        topic: String,
+        online: String,
+        offline: String,
+    ) -> Result<Self, ConnectError> {
+        let options = options
+            .set_reconnect_opts(ReconnectOptions::Never)
+            .set_last_will(LastWill {
+                topic: topic.clone(),
+                message: offline.clone(),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            });
+
+        let mut availability = Availability {
+            client: None,
+            options,
+            topic,
+            online,
+            offline,
+        };
+        availability.connect()?;
+        self.availability = Some(availability);
+
+        Ok(self)
+    }
+
+    /// Publish the offline status, e.g. before exiting.
+    pub fn go_offline(&mut self) -> Result<(), TransmitError> {
+        self.availability
+            .as_mut()
+            .map_or(Ok(()), Availability::go_offline)
+    }
+
+    /// Release the key, publish the offline status and disconnect from the broker.
+    ///
+    /// Both are published at least once, and the connections are closed once the broker has
+    /// acknowledged them, waiting for up to 5 seconds. A key made with [`MqttKey::new`] can't tell,
+    /// and disconnects straight away. rumqtt can't send an MQTT DISCONNECT, so the broker also
+    /// publishes the last wills, which repeat the same state.
+    pub fn disconnect(&mut self) -> Result<(), TransmitError> {
+        let deadline = Instant::now() + DISCONNECT_TIMEOUT;
+        let qos = match self.qos {
+            QoS::AtMostOnce => QoS::AtLeastOnce,
+            qos => qos,
+        };
+        let released = self.publish(
+            &Element::untimed(Signal::Off),
+            qos,
+            self.retain.retains(true),
+        );
+        let result = released.and(self.go_offline());
+
+        let key = self.client.take();
+        let availability = self
+            .availability
+            .as_mut()
+            .and_then(|availability| availability.client.take());
+
+        key.into_iter()
+            .chain(availability)
+            .map(|client| client.disconnect(deadline))
+            .fold(result, Result::and)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn publish(&mut self, element: &Element, qos: QoS, retain: bool) -> Result<(), TransmitError> {
+        let client = self.client.as_mut().ok_or(TransmitError::Disconnected)?;
+        let payload = match element.signal() {
+            Signal::On => self.on_payload.render(element, self.sequence),
+            Signal::Off => self.off_payload.render(element, self.sequence),
+        };
+        self.sequence += 1;
+
+        let result = client.publish(self.topic.as_str(), qos, retain, payload);
+        if result == Err(TransmitError::Disconnected) {
+            self.client = None;
         }
+
+        result
     }
+}
 
-    fn send_on(&mut self) {
-        self.client
-            .publish(
-                self.topic.as_str(),
-                QoS::AtLeastOnce,
-                false,
-                self.on_payload.as_str(),
-            )
-            .unwrap();
+impl Key for MqttKey {
+    fn key_down(&mut self) -> Result<(), TransmitError> {
+        self.send(&Element::untimed(Signal::On))
+    }
+
+    fn key_up(&mut self) -> Result<(), TransmitError> {
+        self.send(&Element::untimed(Signal::Off))
+    }
+
+    fn send(&mut self, element: &Element) -> Result<(), TransmitError> {
+        self.publish(element, self.qos, self.retain.retains(false))
     }
 
-    fn send_off(&mut self) {
-        self.client
-            .publish(
-                self.topic.as_str(),
-                QoS::AtLeastOnce,
-                false,
-                self.off_payload.as_str(),
+    fn release(&mut self) -> Result<(), TransmitError> {
+        self.publish(
+            &Element::untimed(Signal::Off),
+            self.qos,
+            self.retain.retains(true),
+        )
+    }
+}
+
+/// Makes a new connection for a key, or `None` if it failed.
+pub type Connector<T> = Box<dyn FnOnce() -> Option<T> + Send>;
+
+/// A key that can connect again after losing its connection.
+pub trait Reconnect {
+    type Connection: Send + 'static;
+
+    fn is_connected(&self) -> bool;
+
+    /// Something that makes a new connection, or `None` if the key can't reconnect. It is called
+    /// on a thread of its own, as connecting blocks.
+    fn connector(&self) -> Option<Connector<Self::Connection>>;
+
+    /// Use a connection made by the connector.
+    fn reconnected(&mut self, connection: Self::Connection);
+}
+
+impl Reconnect for MqttKey {
+    type Connection = MqttConnection;
+
+    fn is_connected(&self) -> bool {
+        MqttKey::is_connected(self)
+    }
+
+    fn connector(&self) -> Option<Connector<Self::Connection>> {
+        let options = self.options.clone()?;
+        let availability = self.availability.as_ref().map(|availability| {
+            (
+                availability.options.clone(),
+                availability.topic.clone(),
+                availability.online.clone(),
             )
-            .unwrap();
+        });
+
+        Some(Box::new(move || {
+            let key = Publisher::start(options).ok()?;
+            // Retry both later if availability can't be published.
+            let availability = match availability {
+                Some((options, topic, online)) => {
+                    Some(Availability::start(options, &topic, &online).ok()?)
+                }
+                None => None,
+            };
+
+            Some(MqttConnection { key, availability })
+        }))
+    }
+
+    fn reconnected(&mut self, connection: MqttConnection) {
+        self.client = Some(connection.key);
+        if let (Some(availability), Some(client)) =
+            (self.availability.as_mut(), connection.availability)
+        {
+            availability.client = Some(client);
+        }
+    }
+}
+
+// Run `connector` on a thread of its own so that a broker that is slow to answer doesn't hold up
+// the executor.
+fn connect_in_background<T: Send + 'static>(
+    connector: Connector<T>,
+) -> impl Future<Item = Option<T>, Error = TransmitError> {
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = sender.send(connector());
+    });
+
+    // The sender is only dropped without sending if the connector panics.
+    receiver.map_err(|_| TransmitError::Disconnected)
+}
+
+/// Reconnect `key` if it has lost its connection, retrying with `backoff` until it succeeds.
+///
+/// The key isn't locked while connecting. Fails with [`TransmitError::Disconnected`] if the key
+/// can't reconnect, e.g. an [`MqttKey`] that was not created with [`MqttKey::connect`], or with
+/// [`TransmitError::Cancelled`] once `handle` is aborted.
+pub fn reconnect<K>(
+    key: Arc<Mutex<K>>,
+    backoff: Backoff,
+    handle: AbortHandle,
+) -> impl Future<Item = (), Error = TransmitError>
+where
+    K: Reconnect + Send + 'static,
+{
+    if handle.is_aborted() {
+        return future::Either::A(future::err(TransmitError::Cancelled));
+    }
+
+    let reconnection = future::loop_fn(0, move |attempt| {
+        let connector = {
+            let guard = key.lock().unwrap();
+            if guard.is_connected() {
+                return future::Either::A(future::ok(future::Loop::Break(())));
+            }
+            match guard.connector() {
+                Some(connector) => connector,
+                None => return future::Either::A(future::err(TransmitError::Disconnected)),
+            }
+        };
+
+        let key = Arc::clone(&key);
+        future::Either::B(
+            connect_in_background(connector).and_then(move |connection| match connection {
+                Some(connection) => {
+                    key.lock().unwrap().reconnected(connection);
+                    future::Either::A(future::ok(future::Loop::Break(())))
+                }
+                None => future::Either::B(
+                    Delay::new(Instant::now() + backoff.delay(attempt))
+                        .map_err(|_| TransmitError::Cancelled)
+                        .map(move |_| future::Loop::Continue(attempt.saturating_add(1))),
+                ),
+            }),
+        )
+    });
+
+    // Stop waiting for the broker as soon as the transmission is aborted.
+    future::Either::B(
+        reconnection
+            .select2(Aborted(handle))
+            .then(|result| match result {
+                Ok(future::Either::A(_)) => Ok(()),
+                Ok(future::Either::B(_)) => Err(TransmitError::Cancelled),
+                Err(future::Either::A((e, _))) | Err(future::Either::B((e, _))) => Err(e),
+            }),
+    )
+}
+
+/// A key that records each call with a timestamp from `clock`, for testing.
+#[allow(clippy::module_name_repetitions)]
+pub struct RecordingKey<C: Clock> {
+    clock: C,
+    transitions: Vec<(Signal, Instant)>,
+    flushes: usize,
+}
+
+impl<C: Clock> RecordingKey<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            transitions: Vec::new(),
+            flushes: 0,
+        }
+    }
+
+    /// Each key down and key up, in order.
+    pub fn transitions(&self) -> &[(Signal, Instant)] {
+        &self.transitions
+    }
+
+    /// How long each signal lasted, from one transition to the next.
+    pub fn durations(&self) -> Vec<(Signal, Duration)> {
+        self.transitions
+            .iter()
+            .tuple_windows()
+            .map(|(&(signal, start), &(_, end))| (signal, end.duration_since(start)))
+            .collect()
+    }
+
+    pub fn flushes(&self) -> usize {
+        self.flushes
+    }
+}
+
+impl<C: Clock> Key for RecordingKey<C> {
+    fn key_down(&mut self) -> Result<(), TransmitError> {
+        self.transitions.push((Signal::On, self.clock.now()));
+        Ok(())
+    }
+
+    fn key_up(&mut self) -> Result<(), TransmitError> {
+        self.transitions.push((Signal::Off, self.clock.now()));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), TransmitError> {
+        self.flushes += 1;
+        Ok(())
     }
 }
 
@@ -58,10 +737,7 @@ pub fn progress_bar(message: &str, length: usize) -> ProgressBar {
     let pb = ProgressBar::new(length.try_into().unwrap());
     pb.set_style(
         ProgressStyle::default_bar()
-            .template(&format!(
-                "📨 Transmitting: {} [ {{prefix:.cyan.bold}} ] {{wide_bar:.cyan/blue}}",
-                message
-            ))
+            .template("📨 Transmitting: {msg} [ {prefix:.cyan.bold} ] {wide_bar:.cyan/blue}")
             .progress_chars("##-"),
     );
 
@@ -69,88 +745,1032 @@ pub fn progress_bar(message: &str, length: usize) -> ProgressBar {
     pb.set_prefix(" ");
 
     // We can simply change the style when the transmission is complete.
-    pb.set_message(&format!("📬 Transmitted: {}", message));
+    pb.set_message(message);
 
     pb
 }
 
-pub fn transmit_with_dur(
-    key: Arc<Mutex<MqttKey>>,
-    timing: impl Iterator<Item = Signal>,
-    dur: Duration,
+fn finish_progress_bar(pb: &ProgressBar, template: &str) {
+    pb.set_style(ProgressStyle::default_bar().template(template));
+    pb.finish();
+}
+
+#[allow(clippy::non_ascii_literal)]
+fn complete_progress_bar(pb: &ProgressBar) {
+    finish_progress_bar(pb, "📬 Transmitted: {msg}");
+}
+
+/// Finish `pb` showing that the transmission did not complete.
+#[allow(clippy::non_ascii_literal)]
+pub fn abandon_progress_bar(pb: &ProgressBar) {
+    finish_progress_bar(pb, "📭 Interrupted: {msg}");
+}
+
+/// Planned and actual timing of a transmission.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Report {
+    /// Time the transmission should take at the requested speed.
+    pub planned: Duration,
+    /// Time from the first key down to the final key up.
+    pub actual: Duration,
+    /// Largest delay of a key down or key up after its deadline.
+    pub max_lateness: Duration,
+    /// Characters sent in full, as written in each element's [`character`](Element::character). A
+    /// letter is complete once its final mark has ended, and a prosign counts as written, e.g. 4
+    /// for `<SK>`. A word gap counts as the space between words.
+    pub characters: usize,
+    /// Whether the transmission was stopped with an [`AbortHandle`].
+    pub aborted: bool,
+}
+
+#[derive(Default)]
+struct AbortState {
+    aborted: bool,
+    characters: usize,
+    task: Option<task::Task>,
+}
+
+/// Stops a transmission part way through and tracks how far it got.
+///
+/// The key is released and the transmission resolves with [`Report::aborted`] set. Clones share
+/// the same transmission.
+#[derive(Clone, Default)]
+pub struct AbortHandle {
+    state: Arc<Mutex<AbortState>>,
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn abort(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.aborted = true;
+        if let Some(task) = state.task.take() {
+            task.notify();
+        }
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.state.lock().unwrap().aborted
+    }
+
+    /// Characters sent in full so far, as counted by [`Report::characters`]. Each transmission
+    /// with the handle, e.g. a retransmission, counts from zero.
+    pub fn characters(&self) -> usize {
+        self.state.lock().unwrap().characters
+    }
+
+    fn add_characters(&self, characters: usize) {
+        self.state.lock().unwrap().characters += characters;
+    }
+
+    fn reset_characters(&self) {
+        self.state.lock().unwrap().characters = 0;
+    }
+}
+
+// Completes when the handle is aborted.
+struct Aborted(AbortHandle);
+
+impl Future for Aborted {
+    type Item = ();
+    type Error = TransmitError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut state = self.0.state.lock().unwrap();
+        if state.aborted {
+            Ok(Async::Ready(()))
+        } else {
+            state.task = Some(task::current());
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+// The character each symbol is part of, as described for `Element`.
+fn characters(symbols: &[Symbol]) -> Vec<String> {
+    let mut characters = Vec::with_capacity(symbols.len());
+    let mut marks = String::new();
+
+    for (i, symbol) in symbols.iter().enumerate() {
+        match symbol {
+            Symbol::Dot => marks.push('.'),
+            Symbol::Dash => marks.push('-'),
+            _ => {}
+        }
+
+        let end = matches!(symbol, Symbol::LetterGap | Symbol::WordGap);
+        if end || i + 1 == symbols.len() {
+            let letter =
+                Code::from_morse(&marks).map_or_else(|_| String::new(), |code| code.text());
+            marks.clear();
+
+            if *symbol == Symbol::WordGap {
+                characters.resize(i, letter);
+                characters.push(" ".to_string());
+            } else {
+                characters.resize(i + 1, letter);
+            }
+        }
+    }
+
+    characters
+}
+
+/// Send `symbols` with `key` at `speed`, timing each from `clock`, e.g.
+/// [`TokioClock`](crate::clock::TokioClock).
+///
+/// With a `handle`, the transmission can be aborted and how far it got is tracked. Each element
+/// is sent with the character it is part of, decoded from the symbols, so codes shared by a
+/// prosign and a character are given as the prosign.
+pub fn transmit<K: Key, C: Clock>(
+    key: Arc<Mutex<K>>,
+    symbols: impl IntoIterator<Item = Symbol>,
+    speed: Speed,
     progress_bar: Option<ProgressBar>,
-) -> impl Future<Item = (), Error = ()> {
-    // We need to force evaluation since group_by() is lazy
-    let groups: Vec<_> = timing
-        .group_by(|x| *x)
-        .into_iter()
-        .map(|(signal, group)| {
+    clock: C,
+    handle: Option<AbortHandle>,
+) -> impl Future<Item = Report, Error = TransmitError> {
+    let handle = handle.unwrap_or_default();
+    let symbols: Vec<_> = symbols.into_iter().collect();
+    let characters = characters(&symbols);
+
+    // Letter and word gaps follow a complete letter. A word gap also counts as a space.
+    let previous = |i: usize| {
+        i.checked_sub(1)
+            .map_or(0, |p| characters[p].chars().count())
+    };
+    let completes = |i: usize| match symbols[i] {
+        Symbol::LetterGap => previous(i),
+        Symbol::WordGap => previous(i) + 1,
+        _ => 0,
+    };
+    let last_letter = characters.last().map_or(0, |c| c.chars().count());
+
+    let mut offset = Duration::from_secs(0);
+    let groups: Vec<_> = symbols
+        .iter()
+        .zip(characters.iter().cloned())
+        .enumerate()
+        .map(|(i, (&symbol, character))| {
+            let begin = offset;
+            offset += speed.duration(symbol);
+
+            let element = Element {
+                kind: match symbol {
+                    Symbol::Dot => ElementKind::Dot,
+                    Symbol::Dash => ElementKind::Dash,
+                    _ => ElementKind::Gap,
+                },
+                duration: offset - begin,
+                character,
+            };
+
             (
                 Arc::clone(&key),
-                signal,
-                u32::try_from(group.count()).unwrap(),
+                progress_bar.clone(),
+                element,
+                symbol,
+                completes(i),
+                begin,
+                offset,
             )
         })
         .collect();
+    let planned = offset;
 
     if groups.is_empty() {
-        future::Either::A(future::ok(()))
+        future::Either::A(future::ok(Report::default()))
     } else {
-        if let Some(pb) = progress_bar {
-            key.lock().unwrap().deref_mut().progress.replace(pb);
-        };
+        future::Either::B(future::lazy(move || {
+            handle.reset_characters();
+
+            // Deadlines are relative to the start of the transmission so that time spent publishing
+            // doesn't accumulate over the message.
+            let start = clock.now();
+            let end_clock = clock.clone();
+            let max_lateness = Arc::new(Mutex::new(Duration::from_secs(0)));
+            let group_lateness = Arc::clone(&max_lateness);
+            let group_handle = handle.clone();
+
+            stream::iter_ok(groups)
+                .for_each(move |(k, pb, element, symbol, completes, begin, end)| {
+                    // A clock that never waits would otherwise finish before an abort is noticed.
+                    if group_handle.is_aborted() {
+                        return future::Either::A(future::err(TransmitError::Cancelled));
+                    }
 
-        future::Either::B(
-            stream::iter_ok(groups.into_iter())
-                .for_each(move |(k, signal, count)| {
                     {
-                        let mut guard = k.lock().unwrap();
-                        let key = guard.deref_mut();
-
-                        let mark = if signal == Signal::On {
-                            key.send_on();
-
-                            // This is kind of hacky and depends on the fact that a dot is set to length 1.
-                            if count == 1 {
-                                "."
-                            } else {
-                                "-"
-                            }
-                        } else {
-                            key.send_off();
-
-                            " "
-                        };
-
-                        if let Some(pb) = key.progress.as_ref() {
-                            pb.set_prefix(mark);
-                        }
+                        let lateness = clock.now().saturating_duration_since(start + begin);
+                        let mut max_lateness = group_lateness.lock().unwrap();
+                        *max_lateness = lateness.max(*max_lateness);
                     }
 
-                    Delay::new(Instant::now() + count * dur).and_then(move |_| {
-                        let mut guard = k.lock().unwrap();
+                    if let Err(e) = k.lock().unwrap().send(&element) {
+                        return future::Either::A(future::err(e));
+                    }
+                    let mark = match element.kind {
+                        ElementKind::Dot => ".",
+                        ElementKind::Dash => "-",
+                        ElementKind::Gap => " ",
+                    };
 
-                        if let Some(pb) = guard.deref_mut().progress.as_ref() {
-                            pb.inc(count.into());
-                        }
+                    group_handle.add_characters(completes);
+
+                    if let Some(pb) = pb.as_ref() {
+                        pb.set_prefix(mark);
+                    }
 
-                        future::ok(())
-                    })
+                    // The timer only fails when the runtime is shutting down.
+                    future::Either::B(
+                        clock
+                            .delay_until(start + end)
+                            .map_err(|_| TransmitError::Cancelled)
+                            .map(move |_| {
+                                if let Some(pb) = pb.as_ref() {
+                                    pb.inc(symbol.units().into());
+                                }
+                            }),
+                    )
                 })
-                .and_then(move |_| {
-                    let mut guard = key.lock().unwrap();
+                .select2(Aborted(handle.clone()))
+                .then(move |result| {
+                    let actual = end_clock.now().duration_since(start);
+                    let (aborted, result) = match result {
+                        Ok(future::Either::A(_)) => {
+                            // The last letter is only complete once its final mark has ended.
+                            handle.add_characters(last_letter);
+                            (false, Ok(()))
+                        }
+                        Ok(future::Either::B(_)) => (true, Ok(())),
+                        // An abort noticed between marks stops the stream with this error. Any
+                        // other error is reported, even if the transmission was also aborted.
+                        Err(future::Either::A((TransmitError::Cancelled, _)))
+                            if handle.is_aborted() =>
+                        {
+                            (true, Ok(()))
+                        }
+                        Err(future::Either::A((e, _))) | Err(future::Either::B((e, _))) => {
+                            (false, Err(e))
+                        }
+                    };
 
+                    let mut guard = key.lock().unwrap();
                     let key = guard.deref_mut();
-                    key.send_off();
 
-                    if let Some(pb) = key.progress.take() {
-                        pb.set_style(ProgressStyle::default_bar().template("{msg}"));
-                        pb.finish();
+                    // Always try to release the key, even if it failed before.
+                    let released = key.release();
+                    let result = result
+                        .and(released)
+                        .and_then(|_| key.flush())
+                        .map(|_| Report {
+                            planned,
+                            actual,
+                            max_lateness: *max_lateness.lock().unwrap(),
+                            characters: handle.characters(),
+                            aborted,
+                        });
+
+                    if let Some(pb) = progress_bar {
+                        match result {
+                            Ok(Report { aborted: false, .. }) => complete_progress_bar(&pb),
+                            _ => abandon_progress_bar(&pb),
+                        }
                     }
 
-                    future::ok(())
+                    result
                 })
-                .map_err(|_| ()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        reconnect, transmit, AbortHandle, Backoff, Connector, Element, ElementKind, InFlight, Key,
+        Reconnect, RecordingKey, Report, Retain, TransmitError,
+    };
+    use crate::clock::{Clock, MockClock, TokioClock};
+    use crate::code::Code;
+    use crate::decode;
+    use crate::timing::{Profile, Signal, Speed, Symbol};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio::prelude::*;
+
+    // A key whose connector blocks until told whether the connection succeeded.
+    struct BlockingKey {
+        connected: bool,
+        results: Option<Arc<Mutex<mpsc::Receiver<bool>>>>,
+        attempts: Arc<Mutex<usize>>,
+    }
+
+    impl Reconnect for BlockingKey {
+        type Connection = ();
+
+        fn is_connected(&self) -> bool {
+            self.connected
+        }
+
+        fn connector(&self) -> Option<Connector<()>> {
+            let results = Arc::clone(self.results.as_ref()?);
+            let attempts = Arc::clone(&self.attempts);
+            Some(Box::new(move || {
+                *attempts.lock().unwrap() += 1;
+                Some(()).filter(|_| results.lock().unwrap().recv().unwrap())
+            }))
+        }
+
+        fn reconnected(&mut self, _: ()) {
+            self.connected = true;
+        }
+    }
+
+    #[derive(Default)]
+    struct VecKey {
+        signals: Vec<Signal>,
+        flushed: bool,
+    }
+
+    impl Key for VecKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            self.signals.push(Signal::On);
+            Ok(())
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            self.signals.push(Signal::Off);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), TransmitError> {
+            self.flushed = true;
+            Ok(())
+        }
+    }
+
+    // A key that takes time to publish each transition.
+    struct SlowKey {
+        key: RecordingKey<MockClock>,
+        clock: MockClock,
+        latency: Duration,
+    }
+
+    impl Key for SlowKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            let result = self.key.key_down();
+            self.clock.advance(self.latency);
+            result
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            let result = self.key.key_up();
+            self.clock.advance(self.latency);
+            result
+        }
+
+        fn flush(&mut self) -> Result<(), TransmitError> {
+            self.key.flush()
+        }
+    }
+
+    // A key that aborts its transmission after a number of key downs, optionally failing the last.
+    struct AbortingKey {
+        key: RecordingKey<MockClock>,
+        handle: AbortHandle,
+        key_downs: usize,
+        error: Option<TransmitError>,
+    }
+
+    impl Key for AbortingKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            self.key_downs -= 1;
+            if self.key_downs == 0 {
+                self.handle.abort();
+                if let Some(e) = self.error.take() {
+                    return Err(e);
+                }
+            }
+            self.key.key_down()
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            self.key.key_up()
+        }
+
+        fn flush(&mut self) -> Result<(), TransmitError> {
+            self.key.flush()
+        }
+    }
+
+    // A key that loses its connection after a number of key downs.
+    struct FailingKey {
+        key: RecordingKey<MockClock>,
+        key_downs: usize,
+    }
+
+    impl Key for FailingKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            if self.key_downs == 0 {
+                return Err(TransmitError::Disconnected);
+            }
+            self.key_downs -= 1;
+            self.key.key_down()
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            self.key.key_up()
+        }
+
+        fn flush(&mut self) -> Result<(), TransmitError> {
+            self.key.flush()
+        }
+    }
+
+    // A key that records whether each publish would be retained, as MqttKey decides it.
+    struct RetainKey {
+        retain: Retain,
+        publishes: Vec<(Signal, bool)>,
+    }
+
+    impl Key for RetainKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            self.publishes
+                .push((Signal::On, self.retain.retains(false)));
+            Ok(())
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            self.publishes
+                .push((Signal::Off, self.retain.retains(false)));
+            Ok(())
+        }
+
+        fn release(&mut self) -> Result<(), TransmitError> {
+            self.publishes
+                .push((Signal::Off, self.retain.retains(true)));
+            Ok(())
+        }
+    }
+
+    // A key that records the elements it is sent.
+    #[derive(Default)]
+    struct ElementKey {
+        elements: Vec<Element>,
+    }
+
+    impl Key for ElementKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            Ok(())
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            Ok(())
+        }
+
+        fn send(&mut self, element: &Element) -> Result<(), TransmitError> {
+            self.elements.push(element.clone());
+            Ok(())
+        }
+    }
+
+    fn transmit_with_latency(
+        text: &str,
+        speed: Speed,
+        clock: &MockClock,
+        latency: Duration,
+    ) -> (RecordingKey<MockClock>, Report) {
+        let key = Arc::new(Mutex::new(SlowKey {
+            key: RecordingKey::new(clock.clone()),
+            clock: clock.clone(),
+            latency,
+        }));
+        let code: Code = text.parse().unwrap();
+
+        let report = transmit(
+            Arc::clone(&key),
+            code.symbols(),
+            speed,
+            None,
+            clock.clone(),
+            None,
+        )
+        .wait()
+        .unwrap();
+
+        let key = Arc::try_unwrap(key).ok().unwrap().into_inner().unwrap();
+        (key.key, report)
+    }
+
+    fn transmit_text(text: &str, speed: Speed, clock: &MockClock) -> RecordingKey<MockClock> {
+        transmit_with_latency(text, speed, clock, Duration::from_secs(0)).0
+    }
+
+    #[test]
+    fn transmit_generic_key() {
+        let key = Arc::new(Mutex::new(VecKey::default()));
+        let code: Code = "EE".parse().unwrap();
+
+        tokio::runtime::current_thread::block_on_all(transmit(
+            Arc::clone(&key),
+            code.symbols(),
+            Speed::new(Duration::from_millis(1)),
+            None,
+            TokioClock,
+            None,
+        ))
+        .unwrap();
+
+        let key = key.lock().unwrap();
+        assert_eq!(
+            key.signals,
+            vec![Signal::On, Signal::Off, Signal::On, Signal::Off]
+        );
+        assert!(key.flushed);
+    }
+
+    #[test]
+    fn transmit_elements() {
+        let key = Arc::new(Mutex::new(ElementKey::default()));
+        let code: Code = "A E".parse().unwrap();
+
+        transmit(
+            Arc::clone(&key),
+            code.symbols(),
+            Speed::new(Duration::from_millis(50)),
+            None,
+            MockClock::default(),
+            None,
+        )
+        .wait()
+        .unwrap();
+
+        let element = |kind, ms, character: &str| Element {
+            kind,
+            duration: Duration::from_millis(ms),
+            character: character.to_string(),
+        };
+        assert_eq!(
+            key.lock().unwrap().elements,
+            [
+                element(ElementKind::Dot, 50, "A"),
+                element(ElementKind::Gap, 50, "A"),
+                element(ElementKind::Dash, 150, "A"),
+                element(ElementKind::Gap, 350, " "),
+                element(ElementKind::Dot, 50, "E"),
+            ]
+        );
+
+        // The code of `+` is also that of `<AR>`, which it is decoded as.
+        let key = Arc::new(Mutex::new(ElementKey::default()));
+        let code: Code = "+".parse().unwrap();
+        transmit(
+            Arc::clone(&key),
+            code.symbols(),
+            Speed::new(Duration::from_millis(50)),
+            None,
+            MockClock::default(),
+            None,
+        )
+        .wait()
+        .unwrap();
+        assert!(key
+            .lock()
+            .unwrap()
+            .elements
+            .iter()
+            .all(|e| e.character == "<AR>"));
+    }
+
+    #[test]
+    fn transmit_sos() {
+        let start = Instant::now();
+        let clock = MockClock::new(start);
+        let unit = Duration::from_millis(50);
+
+        let key = transmit_text("SOS", Speed::new(unit), &clock);
+
+        let expected = [
+            (Signal::On, 0),
+            (Signal::Off, 1),
+            (Signal::On, 2),
+            (Signal::Off, 3),
+            (Signal::On, 4),
+            (Signal::Off, 5),
+            (Signal::On, 8),
+            (Signal::Off, 11),
+            (Signal::On, 12),
+            (Signal::Off, 15),
+            (Signal::On, 16),
+            (Signal::Off, 19),
+            (Signal::On, 22),
+            (Signal::Off, 23),
+            (Signal::On, 24),
+            (Signal::Off, 25),
+            (Signal::On, 26),
+            (Signal::Off, 27),
+        ]
+        .iter()
+        .map(|&(signal, units)| (signal, start + unit * units))
+        .collect::<Vec<_>>();
+
+        assert_eq!(key.transitions(), expected.as_slice());
+        assert_eq!(key.flushes(), 1);
+        assert_eq!(clock.now(), start + unit * 27);
+    }
+
+    #[test]
+    fn transmit_without_drift() {
+        let start = Instant::now();
+        let clock = MockClock::new(start);
+        let speed = Speed::new(Duration::from_millis(50));
+        let code: Code = "PARIS PARIS".parse().unwrap();
+
+        let (key, report) =
+            transmit_with_latency("PARIS PARIS", speed, &clock, Duration::from_millis(5));
+
+        // Every transition happens on time despite the time spent publishing.
+        let mut at = start;
+        for ((signal, t), symbol) in key.transitions().iter().zip(code.symbols()) {
+            assert_eq!((*signal, *t), (symbol.signal(), at));
+            at += speed.duration(symbol);
+        }
+
+        assert_eq!(report.planned, code.transmission_time(&speed));
+        assert_eq!(report.actual, report.planned);
+        assert_eq!(report.max_lateness, Duration::from_secs(0));
+        assert_eq!(report.characters, "PARIS PARIS".len());
+        assert!(!report.aborted);
+    }
+
+    #[test]
+    fn transmit_late() {
+        let clock = MockClock::default();
+        let speed = Speed::new(Duration::from_millis(50));
+
+        // Publishing takes longer than a dot, so the following transition is late, but the
+        // transmission catches up.
+        let (key, report) = transmit_with_latency("EO", speed, &clock, Duration::from_millis(70));
+
+        assert_eq!(report.max_lateness, Duration::from_millis(20));
+        assert_eq!(report.actual, report.planned);
+        assert_eq!(key.transitions().len(), 8);
+    }
+
+    #[test]
+    fn transmit_abort() {
+        let clock = MockClock::default();
+        let handle = AbortHandle::new();
+        let key = Arc::new(Mutex::new(AbortingKey {
+            key: RecordingKey::new(clock.clone()),
+            handle: handle.clone(),
+            key_downs: 4,
+            error: None,
+        }));
+        let code: Code = "SOS".parse().unwrap();
+
+        let report = transmit(
+            Arc::clone(&key),
+            code.symbols(),
+            Speed::new(Duration::from_millis(50)),
+            None,
+            clock,
+            Some(handle.clone()),
+        )
+        .wait()
+        .unwrap();
+
+        // Stopped during the first dash of 'O', after sending 'S'.
+        assert!(report.aborted);
+        assert_eq!(report.characters, 1);
+        assert_eq!(handle.characters(), 1);
+
+        let key = &key.lock().unwrap().key;
+        assert_eq!(key.transitions().len(), 8);
+        assert_eq!(key.transitions().last().unwrap().0, Signal::Off);
+        assert_eq!(key.flushes(), 1);
+    }
+
+    fn transmit_aborting(
+        text: &str,
+        key_downs: usize,
+        error: Option<TransmitError>,
+    ) -> (AbortHandle, Result<Report, TransmitError>) {
+        let clock = MockClock::default();
+        let handle = AbortHandle::new();
+        let key = Arc::new(Mutex::new(AbortingKey {
+            key: RecordingKey::new(clock.clone()),
+            handle: handle.clone(),
+            key_downs,
+            error,
+        }));
+
+        let result = transmit(
+            key,
+            text.parse::<Code>().unwrap().symbols(),
+            Speed::new(Duration::from_millis(50)),
+            None,
+            clock,
+            Some(handle.clone()),
+        )
+        .wait();
+
+        (handle, result)
+    }
+
+    #[test]
+    fn transmit_prosign_characters() {
+        // Characters are those of the text, so a prosign counts as written.
+        let (_, report) = transmit_aborting("<SK> E", 100, None);
+        assert_eq!(report.unwrap().characters, "<SK> E".len());
+
+        // Stopped on the first 'E', after "<SK>" and the word gap.
+        let (handle, report) = transmit_aborting("<SK> EE", 7, None);
+        assert_eq!(report.unwrap().characters, "<SK> ".len());
+        assert_eq!(handle.characters(), "<SK> ".len());
+    }
+
+    #[test]
+    fn retransmit_characters() {
+        let clock = MockClock::default();
+        let key = Arc::new(Mutex::new(RecordingKey::new(clock.clone())));
+        let handle = AbortHandle::new();
+        let code: Code = "SOS".parse().unwrap();
+
+        for _ in 0..2 {
+            let report = transmit(
+                Arc::clone(&key),
+                code.symbols(),
+                Speed::new(Duration::from_millis(50)),
+                None,
+                clock.clone(),
+                Some(handle.clone()),
+            )
+            .wait()
+            .unwrap();
+            assert_eq!(report.characters, 3);
+            assert_eq!(handle.characters(), 3);
+        }
+    }
+
+    #[test]
+    fn transmit_leading_gap() {
+        let clock = MockClock::default();
+        let key = Arc::new(Mutex::new(RecordingKey::new(clock.clone())));
+        // Valid, if unusual, input for the symbol iterator.
+        let symbols = vec![Symbol::LetterGap, Symbol::WordGap, Symbol::Dot];
+
+        let report = transmit(
+            Arc::clone(&key),
+            symbols,
+            Speed::new(Duration::from_millis(50)),
+            None,
+            clock,
+            None,
+        )
+        .wait()
+        .unwrap();
+        assert_eq!(report.characters, " E".len());
+    }
+
+    #[test]
+    fn transmit_error_while_aborting() {
+        let (handle, result) = transmit_aborting("SOS", 2, Some(TransmitError::Disconnected));
+
+        assert!(handle.is_aborted());
+        assert_eq!(result, Err(TransmitError::Disconnected));
+    }
+
+    #[test]
+    fn transmit_abort_before_start() {
+        let clock = MockClock::default();
+        let key = Arc::new(Mutex::new(RecordingKey::new(clock.clone())));
+        let handle = AbortHandle::new();
+        handle.abort();
+
+        let report = transmit(
+            Arc::clone(&key),
+            "E".parse::<Code>().unwrap().symbols(),
+            Speed::new(Duration::from_millis(50)),
+            None,
+            clock,
+            Some(handle),
         )
+        .wait()
+        .unwrap();
+
+        assert!(report.aborted);
+        assert_eq!(report.characters, 0);
+        assert_eq!(key.lock().unwrap().transitions().len(), 1);
+    }
+
+    #[test]
+    fn transmit_error() {
+        let clock = MockClock::default();
+        let key = Arc::new(Mutex::new(FailingKey {
+            key: RecordingKey::new(clock.clone()),
+            key_downs: 2,
+        }));
+        let code: Code = "SOS".parse().unwrap();
+
+        let result = transmit(
+            Arc::clone(&key),
+            code.symbols(),
+            Speed::new(Duration::from_millis(50)),
+            None,
+            clock,
+            None,
+        )
+        .wait();
+        assert_eq!(result, Err(TransmitError::Disconnected));
+
+        // The key is released after the failure, without a flush.
+        let key = &key.lock().unwrap().key;
+        let signals: Vec<_> = key.transitions().iter().map(|&(s, _)| s).collect();
+        assert_eq!(
+            signals,
+            [
+                Signal::On,
+                Signal::Off,
+                Signal::On,
+                Signal::Off,
+                Signal::Off
+            ]
+        );
+        assert_eq!(key.flushes(), 0);
+    }
+
+    #[test]
+    fn reconnect_without_blocking() {
+        let (sender, receiver) = mpsc::channel();
+        let attempts = Arc::new(Mutex::new(0));
+        let key = Arc::new(Mutex::new(BlockingKey {
+            connected: false,
+            results: Some(Arc::new(Mutex::new(receiver))),
+            attempts: Arc::clone(&attempts),
+        }));
+        let backoff = Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+        };
+
+        // The connector only returns once this has run on the same executor, and it can only lock
+        // the key if reconnecting doesn't hold it.
+        let unlocked = Arc::clone(&key);
+        let other = future::lazy(move || {
+            assert!(unlocked.try_lock().is_ok());
+            sender.send(false).unwrap();
+            sender.send(true).unwrap();
+            Ok(())
+        });
+
+        tokio::runtime::current_thread::block_on_all(
+            reconnect(Arc::clone(&key), backoff, AbortHandle::new()).join(other),
+        )
+        .unwrap();
+
+        assert!(key.lock().unwrap().connected);
+        assert_eq!(*attempts.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn reconnect_unsupported() {
+        let key = Arc::new(Mutex::new(BlockingKey {
+            connected: false,
+            results: None,
+            attempts: Arc::default(),
+        }));
+
+        assert_eq!(
+            reconnect(key, Backoff::default(), AbortHandle::new()).wait(),
+            Err(TransmitError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn reconnect_aborted() {
+        let (sender, receiver) = mpsc::channel();
+        let attempts = Arc::new(Mutex::new(0));
+        let key = Arc::new(Mutex::new(BlockingKey {
+            connected: false,
+            results: Some(Arc::new(Mutex::new(receiver))),
+            attempts: Arc::clone(&attempts),
+        }));
+        let backoff = Backoff {
+            initial: Duration::from_secs(3600),
+            max: Duration::from_secs(3600),
+        };
+
+        let handle = AbortHandle::new();
+        handle.abort();
+        let result = reconnect(Arc::clone(&key), backoff, handle);
+        assert_eq!(result.wait(), Err(TransmitError::Cancelled));
+        assert_eq!(*attempts.lock().unwrap(), 0);
+
+        // Aborting while waiting to retry stops the wait.
+        sender.send(false).unwrap();
+        let handle = AbortHandle::new();
+        let aborter = handle.clone();
+        let aborting = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            aborter.abort();
+        });
+        let result = tokio::runtime::current_thread::block_on_all(reconnect(key, backoff, handle));
+        assert_eq!(result, Err(TransmitError::Cancelled));
+        assert_eq!(*attempts.lock().unwrap(), 1);
+        aborting.join().unwrap();
+    }
+
+    #[test]
+    fn backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(10),
+        };
+
+        assert_eq!(backoff.delay(0), Duration::from_millis(500));
+        assert_eq!(backoff.delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.delay(4), Duration::from_secs(8));
+        assert_eq!(backoff.delay(5), Duration::from_secs(10));
+        assert_eq!(backoff.delay(100), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn retain_from_str() {
+        assert_eq!("never".parse(), Ok(Retain::Never));
+        assert_eq!("Always".parse(), Ok(Retain::Always));
+        assert_eq!("final-off".parse(), Ok(Retain::FinalOff));
+        assert!("final".parse::<Retain>().is_err());
+    }
+
+    #[test]
+    fn transmit_retained() {
+        let publishes = |retain| {
+            let key = Arc::new(Mutex::new(RetainKey {
+                retain,
+                publishes: Vec::new(),
+            }));
+            transmit(
+                Arc::clone(&key),
+                "I".parse::<Code>().unwrap().symbols(),
+                Speed::new(Duration::from_millis(50)),
+                None,
+                MockClock::default(),
+                None,
+            )
+            .wait()
+            .unwrap();
+
+            let key = key.lock().unwrap();
+            key.publishes.clone()
+        };
+
+        // The final key up is the only one retained, and isn't repeated.
+        assert_eq!(
+            publishes(Retain::FinalOff),
+            [
+                (Signal::On, false),
+                (Signal::Off, false),
+                (Signal::On, false),
+                (Signal::Off, true)
+            ]
+        );
+        assert!(publishes(Retain::Always)
+            .iter()
+            .all(|(_, retained)| *retained));
+        assert!(publishes(Retain::Never)
+            .iter()
+            .all(|(_, retained)| !*retained));
+    }
+
+    #[test]
+    fn in_flight_from_str() {
+        assert_eq!("retransmit".parse(), Ok(InFlight::Retransmit));
+        assert_eq!("Abort".parse(), Ok(InFlight::Abort));
+        assert!("resume".parse::<InFlight>().is_err());
+    }
+
+    #[test]
+    fn transmit_empty() {
+        let clock = MockClock::default();
+        let key = transmit_text("", Speed::new(Duration::from_millis(50)), &clock);
+
+        assert!(key.transitions().is_empty());
+        assert_eq!(key.flushes(), 0);
+    }
+
+    #[test]
+    fn transmit_round_trip() {
+        let clock = MockClock::default();
+        let profile = Profile::new(1.0, 3.5, 1.0, 3.0, 7.0).unwrap();
+        let speed = Speed::new(Duration::from_millis(60)).with_profile(profile);
+        let key = transmit_text("MORSE CODE <SK>", speed, &clock);
+
+        assert_eq!(
+            decode::decode(key.durations()).unwrap().text(),
+            "MORSE CODE <SK>"
+        );
     }
 }
@@ -1,6 +1,12 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod clock;
 pub mod code;
+pub mod decode;
+pub mod http;
+mod json;
 pub mod key;
+pub mod queue;
+pub mod template;
 pub mod timing;
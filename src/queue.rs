@@ -0,0 +1,565 @@
+use crate::code::Code;
+use crate::json::write_json_string;
+use crate::key::{AbortHandle, Report, TransmitError};
+use crate::timing::Symbol;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::prelude::*;
+
+/// How urgently a message should be sent. Messages with a higher priority are sent first.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Emergency,
+}
+
+impl FromStr for Priority {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "normal" => Ok(Self::Normal),
+            "high" => Ok(Self::High),
+            "emergency" => Ok(Self::Emergency),
+            _ => Err("expected 'low', 'normal', 'high' or 'emergency'"),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Low => "low",
+                Self::Normal => "normal",
+                Self::High => "high",
+                Self::Emergency => "emergency",
+            }
+        )
+    }
+}
+
+/// A message in a [`Queue`].
+#[derive(Clone)]
+pub struct Job {
+    id: u64,
+    text: String,
+    priority: Priority,
+    symbols: Vec<Symbol>,
+    handle: AbortHandle,
+}
+
+impl Job {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Aborts this job once it is being sent, and tracks how far it got.
+    pub fn handle(&self) -> &AbortHandle {
+        &self.handle
+    }
+}
+
+/// A snapshot of a [`Queue`] for reporting.
+///
+/// Displayed as a JSON object, e.g.
+/// `{"state":"transmitting","text":"SOS","characters":1,"queued":0,"last_error":null}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Status {
+    /// Text of the message being sent and the characters of it sent so far, as counted by
+    /// [`Report::characters`](crate::key::Report::characters).
+    pub transmitting: Option<(String, usize)>,
+    /// Number of messages waiting.
+    pub queued: usize,
+    pub last_error: Option<TransmitError>,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.transmitting {
+            Some((text, characters)) => {
+                write!(f, "{{\"state\":\"transmitting\",\"text\":")?;
+                write_json_string(f, text)?;
+                write!(f, ",\"characters\":{},", characters)?;
+            }
+            None => write!(f, "{{\"state\":\"idle\",")?,
+        }
+
+        write!(f, "\"queued\":{},\"last_error\":", self.queued)?;
+        match &self.last_error {
+            Some(e) => write_json_string(f, &e.to_string())?,
+            None => write!(f, "null")?,
+        }
+        write!(f, "}}")
+    }
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    waiting: VecDeque<Job>,
+    // The job being sent, and whether it was preempted.
+    current: Option<(Job, bool)>,
+    last_error: Option<TransmitError>,
+    closed: bool,
+    task: Option<task::Task>,
+}
+
+impl State {
+    // Jobs are kept in the order they will be sent. A job goes after others of the same priority,
+    // unless it is to be sent `ahead` of them.
+    fn insert(&mut self, job: Job, ahead: bool) {
+        let position = self
+            .waiting
+            .iter()
+            .position(|waiting| {
+                waiting.priority < job.priority || (ahead && waiting.priority == job.priority)
+            })
+            .unwrap_or(self.waiting.len());
+        self.waiting.insert(position, job);
+
+        self.notify();
+    }
+
+    fn notify(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}
+
+/// Messages waiting to be sent with a single key, one at a time.
+///
+/// Clones share the same queue, so messages can be pushed from several sources.
+#[derive(Clone, Default)]
+pub struct Queue {
+    state: Arc<Mutex<State>>,
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `code` to the queue and return its id.
+    ///
+    /// With `preempt`, a message with a lower priority that is being sent is stopped and sent
+    /// again from the start afterwards.
+    pub fn push(&self, code: &Code, priority: Priority, preempt: bool) -> u64 {
+        let mut state = self.state.lock().unwrap();
+
+        let id = state.next_id;
+        state.next_id += 1;
+
+        if let Some((current, preempted)) = state.current.as_mut() {
+            // A message that was cancelled stays cancelled.
+            if preempt && current.priority < priority && !current.handle.is_aborted() {
+                current.handle.abort();
+                *preempted = true;
+            }
+        }
+
+        state.insert(
+            Job {
+                id,
+                text: code.text(),
+                priority,
+                symbols: code.symbols().collect(),
+                handle: AbortHandle::new(),
+            },
+            false,
+        );
+
+        id
+    }
+
+    /// Number of messages waiting, not including the one being sent.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Messages waiting, in the order they will be sent.
+    pub fn waiting(&self) -> Vec<Job> {
+        self.state.lock().unwrap().waiting.iter().cloned().collect()
+    }
+
+    /// The message being sent.
+    pub fn current(&self) -> Option<Job> {
+        let state = self.state.lock().unwrap();
+        state.current.as_ref().map(|(job, _)| job.clone())
+    }
+
+    pub fn status(&self) -> Status {
+        let state = self.state.lock().unwrap();
+
+        Status {
+            transmitting: state
+                .current
+                .as_ref()
+                .map(|(job, _)| (job.text.clone(), job.handle.characters())),
+            queued: state.waiting.len(),
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// The error of the most recent message that failed.
+    pub fn last_error(&self) -> Option<TransmitError> {
+        self.state.lock().unwrap().last_error.clone()
+    }
+
+    /// Remove a waiting message, or abort it if it is being sent. Returns whether it was found.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        match state.current.as_mut() {
+            Some((current, preempted)) if current.id == id => {
+                current.handle.abort();
+                // Don't send it again if it was preempted.
+                *preempted = false;
+                return true;
+            }
+            _ => {}
+        }
+
+        let position = state.waiting.iter().position(|job| job.id == id);
+        position.and_then(|p| state.waiting.remove(p)).is_some()
+    }
+
+    /// Finish once the waiting messages have been sent.
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.notify();
+    }
+
+    /// The next message to send, which becomes the current message. Resolves to `None` once the
+    /// queue is closed and empty.
+    pub fn next(&self) -> impl Future<Item = Option<Job>, Error = ()> {
+        let state = Arc::clone(&self.state);
+
+        future::poll_fn(move || {
+            let mut state = state.lock().unwrap();
+
+            if let Some(job) = state.waiting.pop_front() {
+                state.current = Some((job.clone(), false));
+                Ok(Async::Ready(Some(job)))
+            } else if state.closed {
+                Ok(Async::Ready(None))
+            } else {
+                state.task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+        })
+    }
+
+    /// Record the result of sending `job`, which was taken with [`next`](Queue::next).
+    pub fn finish(&self, job: Job, result: &Result<Report, TransmitError>) {
+        let mut state = self.state.lock().unwrap();
+
+        let preempted = match state.current.take() {
+            Some((current, preempted)) if current.id == job.id => preempted,
+            current => {
+                state.current = current;
+                false
+            }
+        };
+
+        match result {
+            Ok(report) if report.aborted && preempted => state.insert(
+                Job {
+                    handle: AbortHandle::new(),
+                    ..job
+                },
+                true,
+            ),
+            Ok(_) => {}
+            Err(e) => state.last_error = Some(e.clone()),
+        }
+    }
+
+    /// Send messages one at a time with `transmit`, until the queue is closed and empty.
+    ///
+    /// `transmit` is called with each message. It should stop when the message's
+    /// [`handle`](Job::handle) is aborted, e.g. by sending it with [`transmit`] and
+    /// that handle.
+    /// Failed messages are recorded as the last error and not sent again.
+    ///
+    /// [`transmit`]: crate::key::transmit
+    pub fn run<F, T>(&self, transmit: F) -> impl Future<Item = (), Error = ()>
+    where
+        F: FnMut(&Job) -> T,
+        T: IntoFuture<Item = Report, Error = TransmitError>,
+    {
+        let queue = self.clone();
+
+        future::loop_fn(transmit, move |mut transmit| {
+            let queue = queue.clone();
+
+            queue.next().and_then(move |job| match job {
+                None => future::Either::A(future::ok(future::Loop::Break(()))),
+                Some(job) => future::Either::B(transmit(&job).into_future().then(move |result| {
+                    queue.finish(job, &result);
+                    Ok(future::Loop::Continue(transmit))
+                })),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Job, Priority, Queue, Status};
+    use crate::clock::MockClock;
+    use crate::code::Code;
+    use crate::key::{transmit, Key, RecordingKey, TransmitError};
+    use crate::timing::Speed;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::prelude::*;
+
+    fn push(queue: &Queue, text: &str, priority: Priority) -> u64 {
+        queue.push(&text.parse::<Code>().unwrap(), priority, false)
+    }
+
+    fn texts(jobs: &[Job]) -> Vec<&str> {
+        jobs.iter().map(Job::text).collect()
+    }
+
+    // Run `queue` until it is empty, returning the text of each message in the order sent.
+    fn run<K: Key>(queue: &Queue, key: K) -> Vec<String> {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let progress_sent = Arc::clone(&sent);
+
+        let key = Arc::new(Mutex::new(key));
+
+        queue.close();
+        queue
+            .run(move |job| {
+                progress_sent.lock().unwrap().push(job.text().to_string());
+                transmit(
+                    Arc::clone(&key),
+                    job.symbols().to_vec(),
+                    Speed::new(Duration::from_millis(50)),
+                    None,
+                    MockClock::default(),
+                    Some(job.handle().clone()),
+                )
+            })
+            .wait()
+            .unwrap();
+
+        Arc::try_unwrap(sent).unwrap().into_inner().unwrap()
+    }
+
+    #[test]
+    fn priority_from_str() {
+        assert_eq!("Emergency".parse(), Ok(Priority::Emergency));
+        assert_eq!("low".parse(), Ok(Priority::Low));
+        assert!("urgent".parse::<Priority>().is_err());
+        assert_eq!(Priority::High.to_string(), "high");
+    }
+
+    #[test]
+    fn priority_order() {
+        let queue = Queue::new();
+        push(&queue, "A", Priority::Low);
+        push(&queue, "B", Priority::Normal);
+        push(&queue, "C", Priority::Emergency);
+        push(&queue, "D", Priority::Normal);
+
+        assert_eq!(queue.len(), 4);
+        assert_eq!(texts(&queue.waiting()), ["C", "B", "D", "A"]);
+    }
+
+    #[test]
+    fn cancel_waiting() {
+        let queue = Queue::new();
+        let a = push(&queue, "A", Priority::Normal);
+        push(&queue, "B", Priority::Normal);
+
+        assert!(queue.cancel(a));
+        assert!(!queue.cancel(a));
+        assert_eq!(texts(&queue.waiting()), ["B"]);
+    }
+
+    #[test]
+    fn run_in_order() {
+        let queue = Queue::new();
+        push(&queue, "A", Priority::Normal);
+        push(&queue, "B", Priority::High);
+        push(&queue, "C", Priority::Normal);
+
+        let sent = run(&queue, RecordingKey::new(MockClock::default()));
+
+        assert_eq!(sent, ["B", "A", "C"]);
+        assert!(queue.is_empty());
+        assert!(queue.current().is_none());
+        assert!(queue.last_error().is_none());
+    }
+
+    #[test]
+    fn status() {
+        let queue = Queue::new();
+        assert_eq!(
+            queue.status().to_string(),
+            r#"{"state":"idle","queued":0,"last_error":null}"#
+        );
+
+        push(&queue, "A", Priority::Normal);
+        assert_eq!(queue.status().queued, 1);
+
+        let status = Status {
+            transmitting: Some(("SAY \"HI\"".to_string(), 2)),
+            queued: 3,
+            last_error: Some(TransmitError::Disconnected),
+        };
+        assert_eq!(
+            status.to_string(),
+            concat!(
+                r#"{"state":"transmitting","text":"SAY \"HI\"","characters":2,"#,
+                r#""queued":3,"last_error":"Disconnected from MQTT broker"}"#
+            )
+        );
+    }
+
+    // A key that pushes a message to its queue on the first key down.
+    struct PushingKey {
+        queue: Queue,
+        message: Option<(&'static str, Priority, bool)>,
+    }
+
+    impl Key for PushingKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            if let Some((text, priority, preempt)) = self.message.take() {
+                self.queue
+                    .push(&text.parse::<Code>().unwrap(), priority, preempt);
+            }
+            Ok(())
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn run_preempt() {
+        let queue = Queue::new();
+        push(&queue, "PARIS", Priority::Normal);
+        push(&queue, "CODEX", Priority::Normal);
+        let key = PushingKey {
+            queue: queue.clone(),
+            message: Some(("SOS", Priority::Emergency, true)),
+        };
+
+        let sent = run(&queue, key);
+
+        // The preempted message is sent again, before others of the same priority.
+        assert_eq!(sent, ["PARIS", "SOS", "PARIS", "CODEX"]);
+    }
+
+    #[test]
+    fn run_without_preempt() {
+        let queue = Queue::new();
+        push(&queue, "PARIS", Priority::Normal);
+        push(&queue, "CODEX", Priority::Normal);
+        let key = PushingKey {
+            queue: queue.clone(),
+            message: Some(("SOS", Priority::Emergency, false)),
+        };
+
+        let sent = run(&queue, key);
+
+        assert_eq!(sent, ["PARIS", "SOS", "CODEX"]);
+    }
+
+    // A key that runs an action on the first key down.
+    struct ActionKey<F: FnMut()> {
+        action: Option<F>,
+    }
+
+    impl<F: FnMut()> Key for ActionKey<F> {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            if let Some(mut action) = self.action.take() {
+                action();
+            }
+            Ok(())
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn run_cancel_preempted() {
+        for &cancel_first in &[false, true] {
+            let queue = Queue::new();
+            let paris = push(&queue, "PARIS", Priority::Normal);
+            let action_queue = queue.clone();
+            let key = ActionKey {
+                action: Some(move || {
+                    let sos = "SOS".parse::<Code>().unwrap();
+                    if cancel_first {
+                        assert!(action_queue.cancel(paris));
+                        action_queue.push(&sos, Priority::Emergency, true);
+                    } else {
+                        action_queue.push(&sos, Priority::Emergency, true);
+                        assert!(action_queue.cancel(paris));
+                    }
+                }),
+            };
+
+            // A cancelled message isn't sent again, whether or not it was also preempted.
+            assert_eq!(run(&queue, key), ["PARIS", "SOS"]);
+        }
+    }
+
+    // A key that can't publish.
+    struct BrokenKey;
+
+    impl Key for BrokenKey {
+        fn key_down(&mut self) -> Result<(), TransmitError> {
+            Err(TransmitError::Disconnected)
+        }
+
+        fn key_up(&mut self) -> Result<(), TransmitError> {
+            Err(TransmitError::Disconnected)
+        }
+    }
+
+    #[test]
+    fn run_error() {
+        let queue = Queue::new();
+        push(&queue, "A", Priority::Normal);
+        push(&queue, "B", Priority::Normal);
+
+        // Failed messages are not retried.
+        assert_eq!(run(&queue, BrokenKey), ["A", "B"]);
+        assert_eq!(queue.last_error(), Some(TransmitError::Disconnected));
+    }
+}
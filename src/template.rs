@@ -0,0 +1,175 @@
+use crate::json::write_json_escaped;
+use crate::key::Element;
+use crate::timing::Signal;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Variable {
+    State,
+    On,
+    Duration,
+    Mark,
+    Character,
+    Sequence,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "state" => Some(Self::State),
+            "on" => Some(Self::On),
+            "duration" => Some(Self::Duration),
+            "mark" => Some(Self::Mark),
+            "char" => Some(Self::Character),
+            "seq" => Some(Self::Sequence),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Variable(Variable),
+}
+
+/// A payload with variables that are replaced for each element sent.
+///
+/// * `{state}` is `ON` or `OFF`, and `{on}` is `true` or `false`.
+/// * `{duration}` is the length of the element in milliseconds.
+/// * `{mark}` is `dot`, `dash` or `gap`.
+/// * `{char}` is the character being sent, escaped for a JSON string.
+/// * `{seq}` counts the payloads published, from 0.
+///
+/// Payloads that aren't part of a message are rendered for an
+/// [untimed](crate::key::Element::untimed) element, with a `{duration}` of 0 and an empty
+/// `{char}`: the release after a message, after an error and on disconnection, and the last will.
+/// The last will is rendered once, when connecting, so its `{seq}` is always 0.
+///
+/// Any other text is kept as is, braces included, so JSON can be written directly, e.g.
+/// `{"state":"{state}","duration":{duration}}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn new(s: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+
+        while let Some(open) = rest.find('{') {
+            let variable = rest[open + 1..].find('}').and_then(|close| {
+                Variable::from_name(&rest[open + 1..open + 1 + close]).map(|v| (v, close))
+            });
+
+            match variable {
+                Some((variable, close)) => {
+                    text.push_str(&rest[..open]);
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Variable(variable));
+                    rest = &rest[open + close + 2..];
+                }
+                None => {
+                    text.push_str(&rest[..=open]);
+                    rest = &rest[open + 1..];
+                }
+            }
+        }
+
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Self { parts }
+    }
+
+    pub fn render(&self, element: &Element, sequence: u64) -> String {
+        let mut payload = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => payload.push_str(text),
+                Part::Variable(variable) => match variable {
+                    Variable::State => payload.push_str(match element.signal() {
+                        Signal::On => "ON",
+                        Signal::Off => "OFF",
+                    }),
+                    Variable::On => payload.push_str(match element.signal() {
+                        Signal::On => "true",
+                        Signal::Off => "false",
+                    }),
+                    Variable::Duration => {
+                        payload.push_str(&element.duration.as_millis().to_string())
+                    }
+                    Variable::Mark => payload.push_str(&element.kind.to_string()),
+                    Variable::Character => {
+                        // Writing to a String can't fail.
+                        let _ = write_json_escaped(&mut payload, &element.character);
+                    }
+                    Variable::Sequence => payload.push_str(&sequence.to_string()),
+                },
+            }
+        }
+
+        payload
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Template;
+    use crate::key::{Element, ElementKind};
+    use crate::timing::Signal;
+    use std::time::Duration;
+
+    fn dash(character: &str) -> Element {
+        Element {
+            kind: ElementKind::Dash,
+            duration: Duration::from_millis(150),
+            character: character.to_string(),
+        }
+    }
+
+    #[test]
+    fn fixed() {
+        let template = Template::new(r#"{"state":"ON"}"#);
+
+        assert_eq!(template.render(&dash("T"), 0), r#"{"state":"ON"}"#);
+        assert_eq!(Template::new("").render(&dash("T"), 0), "");
+    }
+
+    #[test]
+    fn variables() {
+        let template = Template::new(
+            r#"{"state":"{state}","on":{on},"ms":{duration},"mark":"{mark}","char":"{char}","seq":{seq}}"#,
+        );
+
+        assert_eq!(
+            template.render(&dash("T"), 3),
+            r#"{"state":"ON","on":true,"ms":150,"mark":"dash","char":"T","seq":3}"#
+        );
+        assert_eq!(
+            template.render(&Element::untimed(Signal::Off), 4),
+            r#"{"state":"OFF","on":false,"ms":0,"mark":"gap","char":"","seq":4}"#
+        );
+    }
+
+    #[test]
+    fn escape_character() {
+        let template = Template::new(r#""{char}""#);
+
+        assert_eq!(template.render(&dash("\""), 0), r#""\"""#);
+        assert_eq!(template.render(&dash("\\\n"), 0), r#""\\\u000a""#);
+        assert_eq!(template.render(&dash("<SK>"), 0), r#""<SK>""#);
+    }
+
+    #[test]
+    fn unknown_variable() {
+        let template = Template::new("{stat}{{state}}{");
+
+        assert_eq!(template.render(&dash("T"), 0), "{stat}{ON}{");
+    }
+}
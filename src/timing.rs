@@ -1,6 +1,9 @@
+use crate::code::Code;
 use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Signal {
     On,
     Off,
@@ -18,3 +21,505 @@ impl fmt::Display for Signal {
         )
     }
 }
+
+/// A mark or gap of Morse code.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Symbol {
+    Dot,
+    Dash,
+    /// Gap between the marks of a letter.
+    MarkGap,
+    LetterGap,
+    WordGap,
+}
+
+impl Symbol {
+    pub fn signal(self) -> Signal {
+        match self {
+            Self::Dot | Self::Dash => Signal::On,
+            Self::MarkGap | Self::LetterGap | Self::WordGap => Signal::Off,
+        }
+    }
+
+    /// Length in units with standard timing.
+    pub fn units(self) -> u32 {
+        match self {
+            Self::Dot | Self::MarkGap => 1,
+            Self::Dash | Self::LetterGap => 3,
+            Self::WordGap => 7,
+        }
+    }
+
+    /// Signal units of `symbols` with standard timing.
+    pub fn timing(symbols: impl IntoIterator<Item = Self>) -> impl Iterator<Item = Signal> {
+        symbols
+            .into_iter()
+            .flat_map(|s| std::iter::repeat(s.signal()).take(s.units() as usize))
+    }
+}
+
+/// Standard word used to define words per minute.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Standard {
+    Paris,
+    Codex,
+}
+
+impl Standard {
+    /// Units in the standard word, including the word gap that follows it.
+    pub fn units(self) -> u32 {
+        match self {
+            Self::Paris => 50,
+            Self::Codex => 60,
+        }
+    }
+
+    /// Units in the standard word that are part of a letter. The rest are letter and word gaps.
+    pub fn character_units(self) -> u32 {
+        match self {
+            Self::Paris => 31,
+            Self::Codex => 41,
+        }
+    }
+
+    /// Dot duration when sending `wpm` standard words per minute, or `None` if `wpm` isn't
+    /// positive or gives a dot or standard word duration that is zero or too long to represent.
+    pub fn unit(self, wpm: f64) -> Option<Duration> {
+        let secs = 60.0 / (wpm * f64::from(self.units()));
+        if wpm > 0.0 {
+            // The standard word must fit too, as it is used for Farnsworth timing.
+            checked_from_secs(secs)
+                .filter(|unit| *unit > Duration::from_secs(0))
+                .filter(|unit| unit.checked_mul(self.units()).is_some())
+        } else {
+            None
+        }
+    }
+}
+
+// Duration::from_secs_f64() panics outside of this range.
+fn checked_from_secs(secs: f64) -> Option<Duration> {
+    if secs >= 0.0 && secs.is_finite() && secs < u64::MAX as f64 {
+        Some(Duration::from_secs_f64(secs))
+    } else {
+        None
+    }
+}
+
+impl FromStr for Standard {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "PARIS" => Ok(Self::Paris),
+            "CODEX" => Ok(Self::Codex),
+            _ => Err("Invalid standard word, expected PARIS or CODEX"),
+        }
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Paris => "PARIS",
+                Self::Codex => "CODEX",
+            }
+        )
+    }
+}
+
+/// Lengths of marks and gaps, in units.
+///
+/// The default is the standard 1:3 dot to dash ratio with 1, 3 and 7 unit gaps between marks,
+/// letters and words. Slow receivers may need heavier weighting.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Profile {
+    dot: f64,
+    dash: f64,
+    mark_gap: f64,
+    letter_gap: f64,
+    word_gap: f64,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            dot: 1.0,
+            dash: 3.0,
+            mark_gap: 1.0,
+            letter_gap: 3.0,
+            word_gap: 7.0,
+        }
+    }
+}
+
+impl Profile {
+    /// Longest length accepted for a mark or gap, in units.
+    pub const MAX_UNITS: f64 = 100.0;
+
+    /// A profile with the given lengths, or `None` unless all are positive and no more than
+    /// `MAX_UNITS`.
+    pub fn new(dot: f64, dash: f64, mark_gap: f64, letter_gap: f64, word_gap: f64) -> Option<Self> {
+        let profile = Self {
+            dot,
+            dash,
+            mark_gap,
+            letter_gap,
+            word_gap,
+        };
+
+        // Written so that NaN is rejected.
+        if [dot, dash, mark_gap, letter_gap, word_gap]
+            .iter()
+            .all(|x| *x > 0.0 && *x <= Self::MAX_UNITS)
+        {
+            Some(profile)
+        } else {
+            None
+        }
+    }
+
+    /// Length of `symbol`.
+    pub fn units(&self, symbol: Symbol) -> f64 {
+        match symbol {
+            Symbol::Dot => self.dot,
+            Symbol::Dash => self.dash,
+            Symbol::MarkGap => self.mark_gap,
+            Symbol::LetterGap => self.letter_gap,
+            Symbol::WordGap => self.word_gap,
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = &'static str;
+
+    /// Parse lengths given as `dot,dash,mark_gap,letter_gap,word_gap`, e.g. `1,3.5,1,3,7`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERR: &str = "Invalid profile, expected five positive lengths of at most 100: dot,dash,mark_gap,letter_gap,word_gap";
+
+        let lengths = s
+            .split(',')
+            .map(|x| x.trim().parse::<f64>().map_err(|_| ERR))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match lengths.as_slice() {
+            &[dot, dash, mark_gap, letter_gap, word_gap] => {
+                Self::new(dot, dash, mark_gap, letter_gap, word_gap).ok_or(ERR)
+            }
+            _ => Err(ERR),
+        }
+    }
+}
+
+/// Durations used to transmit each `Symbol`.
+///
+/// Marks and the gaps between them use the character dot duration. Letter and word gaps use the
+/// gap unit, which is longer than the dot duration with Farnsworth timing. Each is weighted by the
+/// `Profile`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Speed {
+    unit: Duration,
+    gap_unit: Duration,
+    profile: Profile,
+    // Effective dot duration and standard word with Farnsworth timing.
+    effective: Option<(Duration, Standard)>,
+}
+
+impl Speed {
+    pub fn new(unit: Duration) -> Self {
+        Self {
+            unit,
+            gap_unit: unit,
+            profile: Profile::default(),
+            effective: None,
+        }
+    }
+
+    /// Weight marks and gaps with `profile` instead of the default. With Farnsworth timing, the
+    /// gap unit is recalculated so that the effective speed is kept.
+    pub fn with_profile(self, profile: Profile) -> Self {
+        match self.effective {
+            Some((effective, standard)) => {
+                Self::farnsworth_profile(self.unit, effective, standard, profile)
+            }
+            None => Self { profile, ..self },
+        }
+    }
+
+    /// Send `wpm` standard words per minute, or `None` if `wpm` isn't a valid speed.
+    pub fn from_wpm(wpm: f64, standard: Standard) -> Option<Self> {
+        standard.unit(wpm).map(Self::new)
+    }
+
+    /// Send characters with a dot duration of `character` but stretch letter and word gaps so
+    /// that the standard word "PARIS" takes as long as it would with a dot duration of
+    /// `effective`.
+    ///
+    /// If `effective` is shorter than `character`, gaps are not shortened. Nor are they stretched
+    /// if `effective` is too long for the standard word's duration to be represented.
+    pub fn farnsworth(character: Duration, effective: Duration) -> Self {
+        Self::farnsworth_with(character, effective, Standard::Paris)
+    }
+
+    /// Farnsworth timing, as with `farnsworth()`, for the given standard word.
+    pub fn farnsworth_with(character: Duration, effective: Duration, standard: Standard) -> Self {
+        Self::farnsworth_profile(character, effective, standard, Profile::default())
+    }
+
+    fn farnsworth_profile(
+        character: Duration,
+        effective: Duration,
+        standard: Standard,
+        profile: Profile,
+    ) -> Self {
+        // Units of the standard word and its word gap as weighted by the profile, split into
+        // those sent with the character dot duration and the letter and word gaps.
+        let word = standard.to_string().parse::<Code>().unwrap();
+        let (gaps, marks): (Vec<_>, Vec<_>) = word
+            .symbols()
+            .chain(std::iter::once(Symbol::WordGap))
+            .partition(|s| matches!(s, Symbol::LetterGap | Symbol::WordGap));
+        let units =
+            |symbols: Vec<Symbol>| symbols.into_iter().map(|s| profile.units(s)).sum::<f64>();
+        let (gap_units, mark_units) = (units(gaps), units(marks));
+
+        let gap_unit = effective
+            .checked_mul(standard.units())
+            .zip(checked_from_secs(character.as_secs_f64() * mark_units))
+            .and_then(|(word, marks)| word.checked_sub(marks))
+            .map_or(character, |d| {
+                Duration::from_nanos((d.as_nanos() as f64 / gap_units) as u64).max(character)
+            });
+
+        Self {
+            unit: character,
+            gap_unit,
+            profile,
+            effective: Some((effective, standard)),
+        }
+    }
+
+    /// Send characters at `character_wpm` with an overall speed of `effective_wpm`, or `None` if
+    /// either isn't a valid speed.
+    pub fn farnsworth_wpm(
+        character_wpm: f64,
+        effective_wpm: f64,
+        standard: Standard,
+    ) -> Option<Self> {
+        Some(Self::farnsworth_with(
+            standard.unit(character_wpm)?,
+            standard.unit(effective_wpm)?,
+            standard,
+        ))
+    }
+
+    pub fn unit(&self) -> Duration {
+        self.unit
+    }
+
+    pub fn gap_unit(&self) -> Duration {
+        self.gap_unit
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Duration of `symbol`.
+    pub fn duration(&self, symbol: Symbol) -> Duration {
+        let unit = match symbol {
+            Symbol::Dot | Symbol::Dash | Symbol::MarkGap => self.unit,
+            Symbol::LetterGap | Symbol::WordGap => self.gap_unit,
+        };
+
+        unit.mul_f64(self.profile.units(symbol))
+    }
+
+    /// Total time taken to transmit `symbols`.
+    pub fn transmission_time(&self, symbols: impl IntoIterator<Item = Symbol>) -> Duration {
+        symbols.into_iter().map(|s| self.duration(s)).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Profile, Signal, Speed, Standard, Symbol};
+    use crate::code::Code;
+    use std::time::Duration;
+
+    #[test]
+    fn standard_units() {
+        for standard in &[Standard::Paris, Standard::Codex] {
+            let word = standard.to_string().parse::<Code>().unwrap();
+            let letter_gaps = (word.text().len() - 1) * 3;
+            assert_eq!(word.timing().count() + 7, standard.units() as usize);
+            assert_eq!(
+                word.timing().count() - letter_gaps,
+                standard.character_units() as usize
+            );
+        }
+    }
+
+    #[test]
+    fn parse_standard() {
+        assert_eq!("paris".parse::<Standard>(), Ok(Standard::Paris));
+        assert_eq!("CODEX".parse::<Standard>(), Ok(Standard::Codex));
+        assert!("morse".parse::<Standard>().is_err());
+    }
+
+    #[test]
+    fn from_wpm() {
+        assert_eq!(
+            Speed::from_wpm(20.0, Standard::Paris).map(|s| s.unit()),
+            Some(Duration::from_millis(60))
+        );
+        assert_eq!(
+            Speed::from_wpm(20.0, Standard::Codex).map(|s| s.unit()),
+            Some(Duration::from_millis(50))
+        );
+    }
+
+    #[test]
+    fn invalid_wpm() {
+        for &wpm in &[0.0, -5.0, f64::NAN, f64::INFINITY, 1e-300, 1e300] {
+            assert_eq!(Standard::Paris.unit(wpm), None);
+            assert_eq!(Speed::from_wpm(wpm, Standard::Codex), None);
+            assert_eq!(Speed::farnsworth_wpm(20.0, wpm, Standard::Paris), None);
+        }
+
+        // The unit fits, but the standard word doesn't.
+        assert_eq!(Standard::Paris.unit(1e-18), None);
+        assert_eq!(Speed::farnsworth_wpm(20.0, 1e-18, Standard::Paris), None);
+
+        let character = Duration::from_millis(60);
+        let speed = Speed::farnsworth(character, Duration::MAX);
+        assert_eq!(speed.gap_unit(), character);
+    }
+
+    #[test]
+    fn farnsworth_wpm() {
+        assert_eq!(
+            Speed::farnsworth_wpm(20.0, 10.0, Standard::Paris).unwrap(),
+            Speed::farnsworth(Duration::from_millis(60), Duration::from_millis(120))
+        );
+    }
+
+    #[test]
+    fn transmission_time() {
+        // One standard word, without the trailing word gap, takes a minute divided by the WPM.
+        for standard in &[Standard::Paris, Standard::Codex] {
+            let word = standard.to_string().parse::<Code>().unwrap();
+
+            let s = Speed::from_wpm(12.0, *standard).unwrap();
+            let t = s.transmission_time(word.symbols()) + s.duration(Symbol::WordGap);
+            assert!((t.as_secs_f64() - 5.0).abs() < 1e-3);
+
+            let s = Speed::farnsworth_wpm(18.0, 12.0, *standard).unwrap();
+            let t = s.transmission_time(word.symbols()) + s.duration(Symbol::WordGap);
+            assert!((t.as_secs_f64() - 5.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn standard() {
+        let s = Speed::new(Duration::from_millis(50));
+        assert_eq!(s.duration(Symbol::Dash), Duration::from_millis(150));
+        assert_eq!(s.duration(Symbol::MarkGap), Duration::from_millis(50));
+        assert_eq!(s.duration(Symbol::WordGap), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn farnsworth() {
+        // 20 WPM characters at 10 WPM effective.
+        let s = Speed::farnsworth(Duration::from_millis(60), Duration::from_millis(120));
+        assert_eq!(s.unit(), Duration::from_millis(60));
+        assert_eq!(s.gap_unit(), Duration::from_nanos(217_894_736));
+
+        assert_eq!(s.duration(Symbol::Dot), Duration::from_millis(60));
+        assert_eq!(s.duration(Symbol::MarkGap), Duration::from_millis(60));
+        assert_eq!(s.duration(Symbol::LetterGap), s.gap_unit() * 3);
+
+        // The characters and gaps of "PARIS " take 50 effective units.
+        let paris = s.unit() * 31 + s.gap_unit() * 19;
+        assert!((paris.as_secs_f64() - 6.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn farnsworth_faster_than_character() {
+        let s = Speed::farnsworth(Duration::from_millis(60), Duration::from_millis(30));
+        assert_eq!(s.unit(), Duration::from_millis(60));
+        assert_eq!(s.gap_unit(), s.unit());
+    }
+
+    #[test]
+    fn default_profile() {
+        let p = Profile::default();
+        for &symbol in &[
+            Symbol::Dot,
+            Symbol::Dash,
+            Symbol::MarkGap,
+            Symbol::LetterGap,
+            Symbol::WordGap,
+        ] {
+            assert!((p.units(symbol) - f64::from(symbol.units())).abs() < f64::EPSILON);
+        }
+    }
+
+    #[test]
+    fn heavy_profile() {
+        let p = Profile::new(1.0, 3.5, 1.5, 3.0, 7.0).unwrap();
+        let s = Speed::new(Duration::from_millis(100)).with_profile(p);
+        assert_eq!(s.duration(Symbol::Dot), Duration::from_millis(100));
+        assert_eq!(s.duration(Symbol::Dash), Duration::from_millis(350));
+        assert_eq!(s.duration(Symbol::MarkGap), Duration::from_millis(150));
+        assert_eq!(s.duration(Symbol::WordGap), Duration::from_millis(700));
+
+        // "A" is a dot, a mark gap and a dash.
+        let a = "A".parse::<Code>().unwrap();
+        assert_eq!(s.transmission_time(a.symbols()), Duration::from_millis(600));
+    }
+
+    #[test]
+    fn profile_with_farnsworth() {
+        let p = Profile::new(1.0, 3.5, 1.5, 4.0, 7.0).unwrap();
+        let s = Speed::farnsworth(Duration::from_millis(60), Duration::from_millis(120))
+            .with_profile(p);
+        assert_eq!(s.duration(Symbol::LetterGap), s.gap_unit() * 4);
+
+        // The effective speed is kept despite the heavier weighting.
+        let paris = "PARIS".parse::<Code>().unwrap();
+        let t = s.transmission_time(paris.symbols()) + s.duration(Symbol::WordGap);
+        assert!((t.as_secs_f64() - 6.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn parse_profile() {
+        assert_eq!("1,3,1,3,7".parse::<Profile>(), Ok(Profile::default()));
+        assert_eq!(
+            "1, 3.5, 1, 3, 7".parse::<Profile>(),
+            Ok(Profile::new(1.0, 3.5, 1.0, 3.0, 7.0).unwrap())
+        );
+        assert!("1,3,1,3".parse::<Profile>().is_err());
+        assert!("1,3,1,3,-7".parse::<Profile>().is_err());
+        assert!("1,3,1,3,x".parse::<Profile>().is_err());
+        assert!("1,3,1,3,NaN".parse::<Profile>().is_err());
+        assert!("1,3,1,3,1e300".parse::<Profile>().is_err());
+    }
+
+    #[test]
+    fn symbol_timing() {
+        let a = "A".parse::<Code>().unwrap();
+        assert_eq!(
+            a.symbols().collect::<Vec<_>>(),
+            [Symbol::Dot, Symbol::MarkGap, Symbol::Dash]
+        );
+        assert_eq!(
+            Symbol::timing(a.symbols()).collect::<Vec<_>>(),
+            [Signal::On, Signal::Off, Signal::On, Signal::On, Signal::On]
+        );
+    }
+}